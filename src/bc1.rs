@@ -1,50 +1,65 @@
-use crate::private::Sealed;
-use crate::{read_u16_le, Block8, Decoder, Encoder, Rgb8, Rgba8};
+use crate::settings::ErrorMetric;
+use crate::vec3::{add, dot, scale, sub};
+use crate::{
+    read_u16_le, Block8, BlockFormat, Decoder, EncodeSettings, Encoder, Quality, Rgb8, Rgba8,
+};
+
+/// The default alpha threshold of [`EncodeSettings`]. Texels with a lower
+/// alpha value are encoded as transparent.
+pub const DEFAULT_ALPHA_THRESHOLD: u8 = 128;
 
 #[derive(Debug)]
 struct Table {
     colors: [Rgb8; 4],
+    /// Whether index 3 is transparent black.
+    punch_through: bool,
 }
 
 impl Table {
+    /// Creates the table for the 4 color mode, used if `c0 > c1`.
     fn new(colors: [Rgb8; 2]) -> Self {
-        let r0 = colors[0].r as f32;
-        let g0 = colors[0].g as f32;
-        let b0 = colors[0].b as f32;
-        let r1 = colors[1].r as f32;
-        let g1 = colors[1].g as f32;
-        let b1 = colors[1].b as f32;
-
-        let c2 = Rgb8 {
-            r: (r0 * 2.0 / 3.0 + r1 * 1.0 / 3.0) as u8,
-            g: (g0 * 2.0 / 3.0 + g1 * 1.0 / 3.0) as u8,
-            b: (b0 * 2.0 / 3.0 + b1 * 1.0 / 3.0) as u8,
-        };
-        let c3 = Rgb8 {
-            r: (r0 * 1.0 / 3.0 + r1 * 2.0 / 3.0) as u8,
-            g: (g0 * 1.0 / 3.0 + g1 * 2.0 / 3.0) as u8,
-            b: (b0 * 1.0 / 3.0 + b1 * 2.0 / 3.0) as u8,
-        };
+        let c2 = lerp(colors[0], colors[1], |a, b| (2 * a + b + 1) / 3);
+        let c3 = lerp(colors[0], colors[1], |a, b| (a + 2 * b + 1) / 3);
 
         Self {
             colors: [colors[0], colors[1], c2, c3],
+            punch_through: false,
         }
     }
 
-    fn get(&self, index: u8) -> Rgb8 {
+    /// Creates the table for the 3 color mode, used if `c0 <= c1`.
+    fn new_punch_through(colors: [Rgb8; 2]) -> Self {
+        let c2 = lerp(colors[0], colors[1], |a, b| (a + b).div_ceil(2));
+
+        Self {
+            colors: [colors[0], colors[1], c2, Rgb8::MIN],
+            punch_through: true,
+        }
+    }
+
+    fn get(&self, index: u8) -> Rgba8 {
         debug_assert!(index <= 0b11);
-        self.colors[index as usize]
+        let c = self.colors[index as usize];
+
+        if self.punch_through && index == 3 {
+            Rgba8::MIN
+        } else {
+            Rgba8::from_array([c.r, c.g, c.b, 255])
+        }
     }
 
-    fn closest(&self, color: Rgb8) -> u8 {
+    /// Returns the index of the closest opaque color.
+    fn closest(&self, color: [f32; 3], metric: &ErrorMetric) -> u8 {
+        let num_colors = if self.punch_through { 3 } else { 4 };
+
         let mut index = 0;
-        let mut distance = i32::MAX;
+        let mut best = f32::MAX;
 
-        for (i, c) in self.colors.iter().enumerate() {
-            let delta = color.distance(*c).abs();
-            if delta < distance {
+        for (i, c) in self.colors[..num_colors].iter().enumerate() {
+            let delta = metric.color_error(sub(color, to_f32(*c)));
+            if delta < best {
                 index = i;
-                distance = delta;
+                best = delta;
             }
         }
 
@@ -53,28 +68,637 @@ impl Table {
     }
 }
 
-/// Encodes a 16 texels into a single BC1 block.
+/// The number of least-squares refinement passes after the initial fit.
+const REFINE_ITERATIONS: usize = 2;
+
+/// The maximum number of times the texels are reordered in cluster fit.
+const CLUSTER_FIT_ITERATIONS: usize = 8;
+
+/// Encodes a 16 texels into a single opaque BC1 block.
 pub fn encode(input: [Rgb8; 16]) -> Block8 {
-    let (min, max) = find_min_max(input);
+    let settings = EncodeSettings::DEFAULT;
+    let params = Params::new(&settings, [1.0; 16]);
+
+    let best = fit_color(&input, &[true; 16], &settings, &params, false);
+    write_block(best.c0, best.c1, best.indices)
+}
+
+/// Encodes 16 texels into a single BC1 block with 1-bit alpha.
+///
+/// Texels with an alpha value below `alpha_threshold` are encoded as
+/// transparent black, using the 3 color mode. If there are no such texels
+/// this is the same as [`encode`].
+pub fn encode_rgba(input: [Rgba8; 16], alpha_threshold: u8) -> Block8 {
+    let settings = EncodeSettings {
+        alpha_threshold,
+        ..EncodeSettings::DEFAULT
+    };
+
+    encode_with_settings(input, &settings)
+}
+
+/// Encodes 16 texels into a single opaque BC1 block using cluster fit.
+///
+/// The texels are ordered along the principal axis and every split of them
+/// into the index clusters is evaluated with optimal endpoints. This is a lot
+/// slower than [`encode`], but gives better results.
+///
+/// The block may use the 3 color mode, so it is not suitable for the color
+/// section of BC2 and BC3 blocks.
+pub fn encode_cluster_fit(input: [Rgb8; 16]) -> Block8 {
+    let settings = EncodeSettings {
+        quality: Quality::Exhaustive,
+        ..EncodeSettings::DEFAULT
+    };
+
+    let params = Params::new(&settings, [1.0; 16]);
+    let best = fit_color(&input, &[true; 16], &settings, &params, true);
+    write_block(best.c0, best.c1, best.indices)
+}
+
+/// Encodes 16 texels into a single BC1 block with 1-bit alpha using cluster
+/// fit.
+///
+/// See [`encode_cluster_fit`] and [`encode_rgba`].
+pub fn encode_rgba_cluster_fit(input: [Rgba8; 16], alpha_threshold: u8) -> Block8 {
+    let settings = EncodeSettings {
+        quality: Quality::Exhaustive,
+        alpha_threshold,
+        ..EncodeSettings::DEFAULT
+    };
+
+    encode_with_settings(input, &settings)
+}
+
+/// Encodes 16 texels into a single BC1 block with the given settings.
+///
+/// [`Quality::Slow`] and above use cluster fit and may pick the 3 color mode
+/// for opaque blocks.
+pub fn encode_with_settings(input: [Rgba8; 16], settings: &EncodeSettings) -> Block8 {
+    let opaque = input.map(|c| c.a >= settings.alpha_threshold);
+    let rgb = input.map(|c| c.to_rgb8());
+
+    let params = Params::new(settings, [1.0; 16]);
+    let best = fit_color(&rgb, &opaque, settings, &params, true);
+    write_block(best.c0, best.c1, best.indices)
+}
+
+/// Encodes the color section of a BC2 or BC3 block, which always uses the
+/// 4 color mode.
+///
+/// The color error is scaled by alpha if
+/// [`EncodeSettings::weight_color_by_alpha`] is set.
+pub(crate) fn encode_four_color(input: [Rgba8; 16], settings: &EncodeSettings) -> Block8 {
+    let texel_weights = input.map(|c| settings.alpha_weight(c.a));
+    let rgb = input.map(|c| c.to_rgb8());
+
+    let params = Params::new(settings, texel_weights);
+    let best = fit_color(&rgb, &[true; 16], settings, &params, false);
+    write_block(best.c0, best.c1, best.indices)
+}
+
+/// The error metric, the per texel error weights and whether to dither, used
+/// to fit the endpoints of a block.
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    metric: ErrorMetric,
+    /// Scale of the color error of every texel.
+    texel_weights: [f32; 16],
+    dither: bool,
+}
+
+impl Params {
+    fn new(settings: &EncodeSettings, texel_weights: [f32; 16]) -> Self {
+        Self {
+            metric: settings.error_metric(),
+            texel_weights,
+            dither: settings.dither,
+        }
+    }
+}
+
+/// Quantized endpoints and indices of a block.
+#[derive(Copy, Clone, Debug)]
+struct Fit {
+    c0: u16,
+    c1: u16,
+    indices: [u8; 16],
+    error: f32,
+}
+
+/// Fits a block with the method selected by the quality of `settings`.
+fn fit_color(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    settings: &EncodeSettings,
+    params: &Params,
+    allow_three_color: bool,
+) -> Fit {
+    let punch_through = opaque.contains(&false);
+    debug_assert!(allow_three_color || !punch_through);
+
+    match settings.quality {
+        Quality::Fast => fit_block(input, opaque, punch_through, params, 0),
+        Quality::Normal => fit_block(input, opaque, punch_through, params, REFINE_ITERATIONS),
+        Quality::Slow => cluster_fit(input, opaque, allow_three_color, params, 1),
+        Quality::Exhaustive => cluster_fit(
+            input,
+            opaque,
+            allow_three_color,
+            params,
+            CLUSTER_FIT_ITERATIONS,
+        ),
+    }
+}
+
+/// Fits endpoints along the principal axis of all opaque texels and refines
+/// them using least squares.
+fn fit_block(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    punch_through: bool,
+    params: &Params,
+    iterations: usize,
+) -> Fit {
+    let endpoints = principal_endpoints(input, opaque, params);
+    let mut best = fit_endpoints(input, opaque, endpoints, punch_through, params);
+
+    for _ in 0..iterations {
+        let Some(endpoints) = least_squares(input, opaque, &best, params) else {
+            break;
+        };
+
+        let candidate = fit_endpoints(input, opaque, endpoints, punch_through, params);
+        if candidate.error >= best.error {
+            break;
+        }
+
+        best = candidate;
+    }
+
+    best
+}
+
+/// Quantizes the endpoints and selects the indices of all texels.
+///
+/// Texels that are not opaque are always transparent.
+fn fit_endpoints(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    endpoints: [[f32; 3]; 2],
+    punch_through: bool,
+    params: &Params,
+) -> Fit {
+    let [mut c0, mut c1] = endpoints.map(|[r, g, b]| {
+        let to_u8 = |v: f32| (v.clamp(0.0, 255.0) + 0.5) as u8;
+        encode_565_rgb(to_u8(r), to_u8(g), to_u8(b))
+    });
+
+    // The 4 color mode requires `c0 > c1` and the 3 color mode requires
+    // `c0 <= c1`.
+    if punch_through == (c0 > c1) {
+        core::mem::swap(&mut c0, &mut c1);
+    }
+
+    let colors = [decode_565_rgb(c0), decode_565_rgb(c1)];
+    let table = if punch_through {
+        Table::new_punch_through(colors)
+    } else {
+        Table::new(colors)
+    };
+
+    // Quantization error carried over from previous texels if dithering.
+    let mut carry = [[0.0; 3]; 16];
+
+    let mut indices = [0; 16];
+    let mut error = 0.0;
+    for (index, texel) in input.iter().enumerate() {
+        if !opaque[index] {
+            indices[index] = 3;
+            continue;
+        }
+
+        let texel = to_f32(*texel);
+        let target = add(texel, carry[index]);
+
+        // If both endpoints are equal the block uses the 3 color mode, so
+        // only index 0 is valid.
+        let value = if c0 == c1 {
+            0
+        } else {
+            table.closest(target, &params.metric)
+        };
+
+        let color = to_f32(table.colors[value as usize]);
+        if params.dither {
+            diffuse(&mut carry, index, sub(target, color));
+        }
+
+        indices[index] = value;
+        error += params.metric.color_error(sub(texel, color)) * params.texel_weights[index];
+    }
+
+    Fit {
+        c0,
+        c1,
+        indices,
+        error,
+    }
+}
+
+/// Distributes the quantization error of texel `index` to its unvisited
+/// neighbours using Floyd-Steinberg weights.
+fn diffuse(carry: &mut [[f32; 3]; 16], index: usize, error: [f32; 3]) {
+    let (x, y) = (index % 4, index / 4);
+
+    let neighbours = [
+        (x + 1 < 4, index + 1, 7.0 / 16.0),
+        (y < 3 && x > 0, index + 3, 3.0 / 16.0),
+        (y < 3, index + 4, 5.0 / 16.0),
+        (y < 3 && x + 1 < 4, index + 5, 1.0 / 16.0),
+    ];
+
+    for (valid, neighbour, weight) in neighbours {
+        if valid {
+            carry[neighbour] = add(carry[neighbour], scale(error, weight));
+        }
+    }
+}
+
+/// Fits endpoints to every ordered split of the opaque texels into the index
+/// clusters and returns the best block.
+fn cluster_fit(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    allow_three_color: bool,
+    params: &Params,
+    iterations: usize,
+) -> Fit {
+    let has_transparent = opaque.contains(&false);
+
+    // Cluster fit is not guaranteed to beat the simple fit after
+    // quantization, so we keep the better one.
+    let mut best = fit_block(input, opaque, has_transparent, params, REFINE_ITERATIONS);
+
+    // Transparent texels require the 3 color mode, otherwise both are tried
+    // if allowed.
+    let modes: &[bool] = if has_transparent {
+        &[true]
+    } else if allow_three_color {
+        &[false, true]
+    } else {
+        &[false]
+    };
+
+    for &punch_through in modes {
+        let candidate = cluster_fit_mode(input, opaque, punch_through, params, iterations);
+        if candidate.error < best.error {
+            best = candidate;
+        }
+    }
+
+    best
+}
+
+fn cluster_fit_mode(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    punch_through: bool,
+    params: &Params,
+    iterations: usize,
+) -> Fit {
+    let (texels, texel_weights, count) = opaque_texels(input, opaque, params);
+    let texels = &texels[..count];
+    let texel_weights = &texel_weights[..count];
+
+    if count == 0 {
+        return fit_endpoints(input, opaque, [[0.0; 3]; 2], punch_through, params);
+    }
+
+    // Weight of the second endpoint for every cluster, ordered from the
+    // first to the second endpoint.
+    let weights: &[f32] = if punch_through {
+        &[0.0, 0.5, 1.0]
+    } else {
+        &[0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]
+    };
+
+    let (mean, mut axis) = principal_axis(texels, texel_weights, &params.metric);
+    let mut best = (f32::MAX, [mean; 2]);
+    let mut previous_order = None;
+
+    for _ in 0..iterations {
+        let mut order = [0; 16];
+        for (index, value) in order.iter_mut().enumerate() {
+            *value = index;
+        }
+        // Ties are broken by index, since a stable sort requires `alloc`.
+        order[..count].sort_unstable_by(|&a, &b| {
+            dot(texels[a], axis)
+                .total_cmp(&dot(texels[b], axis))
+                .then(a.cmp(&b))
+        });
+
+        // The same order gives the same result.
+        if previous_order == Some(order) {
+            break;
+        }
+        previous_order = Some(order);
+
+        // prefix[i] is the weighted sum of the first i texels in order and
+        // prefix_weights[i] the sum of their weights.
+        let mut prefix = [[0.0; 3]; 17];
+        let mut prefix_weights = [0.0; 17];
+        for (i, &index) in order[..count].iter().enumerate() {
+            let weight = texel_weights[index];
+            prefix[i + 1] = add(prefix[i], scale(texels[index], weight));
+            prefix_weights[i + 1] = prefix_weights[i] + weight;
+        }
+
+        let mut bounds = [0; 5];
+        bounds[weights.len()] = count;
+        let mut improved = false;
+
+        // Enumerate all splits of the ordered texels into the clusters.
+        loop {
+            let candidate =
+                evaluate_clusters(&prefix, &prefix_weights, &bounds, weights, &params.metric);
+            if let Some(candidate) = candidate {
+                if candidate.0 < best.0 {
+                    best = candidate;
+                    improved = true;
+                }
+            }
+
+            if !next_split(&mut bounds[1..weights.len()], count) {
+                break;
+            }
+        }
+
+        if !improved {
+            break;
+        }
+
+        axis = sub(best.1[1], best.1[0]);
+    }
+
+    fit_endpoints(input, opaque, best.1, punch_through, params)
+}
+
+/// Advances the inner cluster bounds to the next non-decreasing sequence.
+/// Returns `false` after the last one.
+fn next_split(bounds: &mut [usize], count: usize) -> bool {
+    for i in (0..bounds.len()).rev() {
+        if bounds[i] < count {
+            bounds[i] += 1;
+            let value = bounds[i];
+            bounds[i + 1..].fill(value);
+
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Returns the error and the optimal quantized endpoints for the given
+/// clusters.
+///
+/// Texels `bounds[i]..bounds[i + 1]` in order use `weights[i]`. The error
+/// omits the constant sum of the squared texels.
+fn evaluate_clusters(
+    prefix: &[[f32; 3]; 17],
+    prefix_weights: &[f32; 17],
+    bounds: &[usize; 5],
+    weights: &[f32],
+    metric: &ErrorMetric,
+) -> Option<(f32, [[f32; 3]; 2])> {
+    let mut aa = 0.0;
+    let mut ab = 0.0;
+    let mut bb = 0.0;
+    let mut ax = [0.0; 3];
+    let mut bx = [0.0; 3];
+
+    for (cluster, &t) in weights.iter().enumerate() {
+        let count = prefix_weights[bounds[cluster + 1]] - prefix_weights[bounds[cluster]];
+        let sum = sub(prefix[bounds[cluster + 1]], prefix[bounds[cluster]]);
+
+        aa += count * (1.0 - t) * (1.0 - t);
+        ab += count * (1.0 - t) * t;
+        bb += count * t * t;
+        ax = add(ax, scale(sum, 1.0 - t));
+        bx = add(bx, scale(sum, t));
+    }
+
+    // All texels share the same metric, so it does not change the solution,
+    // only the error.
+    let det = aa * bb - ab * ab;
+    if det == 0.0 {
+        return None;
+    }
+
+    let a = scale(sub(scale(ax, bb), scale(bx, ab)), 1.0 / det);
+    let b = scale(sub(scale(bx, aa), scale(ax, ab)), 1.0 / det);
+
+    // Evaluate the error on the endpoints the decoder will see.
+    let [a, b] = [a, b].map(snap_to_grid);
+
+    let m = metric;
+    let error = aa * m.dot(a, a) + 2.0 * ab * m.dot(a, b) + bb * m.dot(b, b)
+        - 2.0 * (m.dot(a, ax) + m.dot(b, bx));
+
+    Some((error, [a, b]))
+}
+
+/// Rounds a color to the nearest 5:6:5 color.
+fn snap_to_grid(color: [f32; 3]) -> [f32; 3] {
+    let to_u8 = |v: f32| (v.clamp(0.0, 255.0) + 0.5) as u8;
+    let c = decode_565_rgb(encode_565_rgb(
+        to_u8(color[0]),
+        to_u8(color[1]),
+        to_u8(color[2]),
+    ));
+
+    to_f32(c)
+}
+
+/// Returns the endpoints along the principal axis of all opaque texels.
+fn principal_endpoints(input: &[Rgb8; 16], opaque: &[bool; 16], params: &Params) -> [[f32; 3]; 2] {
+    let (texels, texel_weights, count) = opaque_texels(input, opaque, params);
+    let texels = &texels[..count];
+
+    if count == 0 {
+        return [[0.0; 3]; 2];
+    }
+
+    let (mean, axis) = principal_axis(texels, &texel_weights[..count], &params.metric);
+
+    let length = params.metric.dot(axis, axis);
+    if length == 0.0 {
+        return [mean; 2];
+    }
+
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+    for texel in texels {
+        let t = params.metric.dot(sub(*texel, mean), axis) / length;
+        min = min.min(t);
+        max = max.max(t);
+    }
+
+    [add(mean, scale(axis, max)), add(mean, scale(axis, min))]
+}
+
+/// Returns all opaque texels, their weights and their count.
+fn opaque_texels(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    params: &Params,
+) -> ([[f32; 3]; 16], [f32; 16], usize) {
+    let mut texels = [[0.0; 3]; 16];
+    let mut weights = [0.0; 16];
+    let mut count = 0;
+
+    for (index, texel) in input.iter().enumerate() {
+        if opaque[index] {
+            texels[count] = to_f32(*texel);
+            weights[count] = params.texel_weights[index];
+            count += 1;
+        }
+    }
+
+    (texels, weights, count)
+}
+
+/// Returns the weighted mean and the principal axis of a non-empty set of
+/// texels, measuring the variance with `metric`.
+///
+/// The axis is not normalized and zero if all texels are equal.
+fn principal_axis(
+    texels: &[[f32; 3]],
+    weights: &[f32],
+    metric: &ErrorMetric,
+) -> ([f32; 3], [f32; 3]) {
+    let mut mean = [0.0; 3];
+    let mut total = 0.0;
+    for (texel, weight) in texels.iter().zip(weights) {
+        mean = add(mean, scale(*texel, *weight));
+        total += weight;
+    }
+    let mean = scale(mean, 1.0 / total);
 
-    let c0 = encode_565_rgb(min.r, min.g, min.b);
-    let c1 = encode_565_rgb(max.r, max.g, max.b);
+    let mut covariance = [[0.0; 3]; 3];
+    for (texel, weight) in texels.iter().zip(weights) {
+        let offset = sub(*texel, mean);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += offset[i] * offset[j] * weight;
+            }
+        }
+    }
+
+    // The eigenvectors of the covariance multiplied by the metric are the
+    // axes of the texels in the metric space, mapped back to colors.
+    let covariance = covariance.map(|row| {
+        let mut product = [0.0; 3];
+        for (j, value) in product.iter_mut().enumerate() {
+            *value = (0..3).map(|k| row[k] * metric.color[k][j]).sum();
+        }
+
+        product
+    });
+
+    // Power iteration, starting from the column with the largest variance.
+    // The column is never orthogonal to the principal axis unless the
+    // covariance is zero.
+    let column = (0..3)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap();
+    let mut axis = covariance.map(|row| row[column]);
+    for _ in 0..8 {
+        let next = covariance.map(|row| dot(row, axis));
+
+        let norm = next.iter().fold(0.0f32, |acc, v| acc.max(v.abs()));
+        if norm == 0.0 {
+            break;
+        }
+
+        axis = scale(next, 1.0 / norm);
+    }
+
+    (mean, axis)
+}
+
+/// Returns the endpoints that minimize the squared error for the indices of
+/// `fit`, or `None` if all texels use the same weight.
+fn least_squares(
+    input: &[Rgb8; 16],
+    opaque: &[bool; 16],
+    fit: &Fit,
+    params: &Params,
+) -> Option<[[f32; 3]; 2]> {
+    // Weight of the second endpoint for every index.
+    let weights = if fit.c0 > fit.c1 {
+        [0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0]
+    } else {
+        [0.0, 1.0, 0.5, 0.0]
+    };
+
+    let mut aa = 0.0;
+    let mut ab = 0.0;
+    let mut bb = 0.0;
+    let mut ax = [0.0; 3];
+    let mut bx = [0.0; 3];
+
+    for (index, texel) in input.iter().enumerate() {
+        if !opaque[index] {
+            continue;
+        }
+
+        let t = weights[fit.indices[index] as usize];
+        let w = params.texel_weights[index];
+        let texel = to_f32(*texel);
 
-    let table = Table::new([min, max]);
+        aa += w * (1.0 - t) * (1.0 - t);
+        ab += w * (1.0 - t) * t;
+        bb += w * t * t;
+        ax = add(ax, scale(texel, w * (1.0 - t)));
+        bx = add(bx, scale(texel, w * t));
+    }
+
+    let det = aa * bb - ab * ab;
+    if det == 0.0 {
+        return None;
+    }
+
+    Some([
+        scale(sub(scale(ax, bb), scale(bx, ab)), 1.0 / det),
+        scale(sub(scale(bx, aa), scale(ax, ab)), 1.0 / det),
+    ])
+}
+
+fn to_f32(color: Rgb8) -> [f32; 3] {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+/// Applies `f` to every channel of both colors.
+fn lerp(c0: Rgb8, c1: Rgb8, f: impl Fn(u16, u16) -> u16) -> Rgb8 {
+    Rgb8 {
+        r: f(c0.r as u16, c1.r as u16) as u8,
+        g: f(c0.g as u16, c1.g as u16) as u8,
+        b: f(c0.b as u16, c1.b as u16) as u8,
+    }
+}
 
+fn write_block(c0: u16, c1: u16, indices: [u8; 16]) -> Block8 {
     let mut output = [0; 8];
     output[0] = c0.to_le_bytes()[0];
     output[1] = c0.to_le_bytes()[1];
     output[2] = c1.to_le_bytes()[0];
     output[3] = c1.to_le_bytes()[1];
 
-    for (row, chunk) in input.chunks(4).enumerate() {
-        let f0 = table.closest(chunk[0]);
-        let f1 = table.closest(chunk[1]);
-        let f2 = table.closest(chunk[2]);
-        let f3 = table.closest(chunk[3]);
-
-        let byte = (f0 << 0) | (f1 << 2) | (f2 << 4) | (f3 << 6);
+    for (row, chunk) in indices.chunks(4).enumerate() {
+        let byte = chunk[0] | (chunk[1] << 2) | (chunk[2] << 4) | (chunk[3] << 6);
         output[row + 4] = byte;
     }
 
@@ -82,13 +706,37 @@ pub fn encode(input: [Rgb8; 16]) -> Block8 {
 }
 
 /// Decode a single BC1 block.
+///
+/// Transparent texels are decoded as black.
 pub fn decode(input: Block8) -> [Rgb8; 16] {
-    let c0 = decode_565_rgb(read_u16_le(input[0], input[1]));
-    let c1 = decode_565_rgb(read_u16_le(input[2], input[3]));
+    decode_rgba(input).map(|c| c.to_rgb8())
+}
+
+/// Decode a single BC1 block with 1-bit alpha.
+///
+/// If `c0 <= c1` the block uses 3 colors and index 3 is transparent black.
+pub fn decode_rgba(input: Block8) -> [Rgba8; 16] {
+    decode_block(input, true)
+}
+
+/// Decode the color section of a BC2 or BC3 block, which always uses the
+/// 4 color mode.
+pub(crate) fn decode_four_color(input: Block8) -> [Rgb8; 16] {
+    decode_block(input, false).map(|c| c.to_rgb8())
+}
+
+fn decode_block(input: Block8, allow_punch_through: bool) -> [Rgba8; 16] {
+    let c0 = read_u16_le(input[0], input[1]);
+    let c1 = read_u16_le(input[2], input[3]);
 
-    let table = Table::new([c0, c1]);
+    let colors = [decode_565_rgb(c0), decode_565_rgb(c1)];
+    let table = if allow_punch_through && c0 <= c1 {
+        Table::new_punch_through(colors)
+    } else {
+        Table::new(colors)
+    };
 
-    let mut output = [Rgb8::from_arry([0; 3]); 16];
+    let mut output = [Rgba8::MIN; 16];
     for row in 0..4 {
         let byte = input[row + 4];
 
@@ -100,103 +748,66 @@ pub fn decode(input: Block8) -> [Rgb8; 16] {
         output[row * 4 + 3] = table.get(f0);
         output[row * 4 + 2] = table.get(f1);
         output[row * 4 + 1] = table.get(f2);
-        output[row * 4 + 0] = table.get(f3);
+        output[row * 4] = table.get(f3);
     }
 
     output
 }
 
+/// Quantizes a color to 5:6:5 bits, rounding to nearest.
 fn encode_565_rgb(r: u8, g: u8, b: u8) -> u16 {
-    let r = ((r / 4) as u16) << (5 + 6);
-    let g = ((g / 2) as u16) << 5;
-    let b = (b / 4) as u16;
+    let r = (r as u16 * 31 + 127) / 255;
+    let g = (g as u16 * 63 + 127) / 255;
+    let b = (b as u16 * 31 + 127) / 255;
 
-    r | g | b
+    (r << 11) | (g << 5) | b
 }
 
+/// Expands a 5:6:5 color to 8 bits per channel by replicating the high bits.
 fn decode_565_rgb(rgb: u16) -> Rgb8 {
-    let r = (rgb & 0b1111_1000_0000_0000) >> 11;
-    let g = (rgb & 0b0000_0111_1110_0000) >> 5;
-    let b = rgb & 0b0000_0000_0001_1111;
+    let r = ((rgb & 0b1111_1000_0000_0000) >> 11) as u8;
+    let g = ((rgb & 0b0000_0111_1110_0000) >> 5) as u8;
+    let b = (rgb & 0b0000_0000_0001_1111) as u8;
 
     Rgb8 {
-        r: (r as u8) << 3,
-        g: (g as u8) << 2,
-        b: (b as u8) << 3,
+        r: (r << 3) | (r >> 2),
+        g: (g << 2) | (g >> 4),
+        b: (b << 3) | (b >> 2),
     }
 }
 
-fn find_min_max(input: [Rgb8; 16]) -> (Rgb8, Rgb8) {
-    let mut min = u16::MAX;
-    let mut max = 0;
-
-    let mut min_color = Rgb8::MAX;
-    let mut max_color = Rgb8::MIN;
+pub struct Bc1;
 
-    for color in input {
-        let val = color.r as u16 + color.g as u16 * 2 + color.b as u16;
+impl BlockFormat for Bc1 {
+    type Pixel = Rgba8;
+    type Block = Block8;
 
-        if val < min {
-            min = val;
-            min_color = color;
-        }
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 8;
+}
 
-        if val > max {
-            max = val;
-            max_color = color;
-        }
+impl Decoder for Bc1 {
+    fn decode_block(block: &Block8, out: &mut [Rgba8]) {
+        out[..16].copy_from_slice(&decode_rgba(*block));
     }
-
-    (min_color, max_color)
 }
 
-pub struct Bc1;
-
-impl Decoder for Bc1 {}
-impl Encoder for Bc1 {}
-
-impl Sealed for Bc1 {
-    const BLOCK_SIZE: usize = 8;
-    const NUM_PIXELS: usize = 4;
-
-    fn decode(block: &[u8], out: &mut [crate::Rgba8]) {
-        let block: [u8; Self::BLOCK_SIZE] = block[0..8].try_into().unwrap();
-        let pixels = decode(block);
-
-        for (index, px) in pixels.iter().enumerate() {
-            out[index] = Rgba8::from_array([px.r, px.g, px.b, 255]);
-        }
-    }
-
-    fn encode(input: &[Rgba8], block: &mut [u8]) {
-        let pixels = [
-            input[0].to_rgb8(),
-            input[1].to_rgb8(),
-            input[2].to_rgb8(),
-            input[3].to_rgb8(),
-            input[4].to_rgb8(),
-            input[5].to_rgb8(),
-            input[6].to_rgb8(),
-            input[7].to_rgb8(),
-            input[8].to_rgb8(),
-            input[9].to_rgb8(),
-            input[10].to_rgb8(),
-            input[11].to_rgb8(),
-            input[12].to_rgb8(),
-            input[13].to_rgb8(),
-            input[14].to_rgb8(),
-            input[15].to_rgb8(),
-        ];
-
-        block.copy_from_slice(&encode(pixels));
+impl Encoder for Bc1 {
+    fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block8 {
+        let pixels: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode_with_settings(pixels, settings)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Rgb8;
+    use crate::{bc7, fixtures, EncodeSettings, Metric, Quality, Rgb8, Rgba8};
 
-    use super::{decode, decode_565_rgb, encode};
+    use super::{
+        decode, decode_565_rgb, decode_four_color, decode_rgba, encode, encode_565_rgb,
+        encode_cluster_fit, encode_rgba, encode_rgba_cluster_fit, encode_with_settings,
+    };
 
     #[test]
     fn bc1_encode() {
@@ -223,13 +834,13 @@ mod tests {
         assert_eq!(
             block,
             [
+                0b0110_0001,
+                0b0000_1000,
                 0b0000_0000,
                 0b0000_0000,
-                0b1010_0011,
-                0b0001_0000,
                 0b0010_1101,
-                0b0000_1011,
-                0b0110_1101,
+                0b1011_0101,
+                0b0010_1100,
                 0b0010_1101,
             ]
         );
@@ -238,13 +849,13 @@ mod tests {
     #[test]
     fn bc1_decode() {
         let input = [
+            0b0110_0001,
+            0b0000_1000,
             0b0000_0000,
             0b0000_0000,
-            0b1010_0011,
-            0b0001_0000,
             0b0010_1101,
-            0b0000_1011,
-            0b0110_1101,
+            0b1011_0101,
+            0b0010_1100,
             0b0010_1101,
         ];
 
@@ -253,21 +864,21 @@ mod tests {
             output,
             [
                 Rgb8 { r: 0, g: 0, b: 0 },
-                Rgb8 { r: 2, g: 3, b: 4 },
-                Rgb8 { r: 5, g: 6, b: 8 },
-                Rgb8 { r: 8, g: 10, b: 12 },
+                Rgb8 { r: 3, g: 4, b: 3 },
+                Rgb8 { r: 5, g: 8, b: 5 },
+                Rgb8 { r: 8, g: 12, b: 8 },
                 Rgb8 { r: 0, g: 0, b: 0 },
                 Rgb8 { r: 0, g: 0, b: 0 },
-                Rgb8 { r: 2, g: 3, b: 4 },
-                Rgb8 { r: 5, g: 6, b: 8 },
-                Rgb8 { r: 8, g: 10, b: 12 },
-                Rgb8 { r: 2, g: 3, b: 4 },
-                Rgb8 { r: 5, g: 6, b: 8 },
-                Rgb8 { r: 8, g: 10, b: 12 },
+                Rgb8 { r: 3, g: 4, b: 3 },
+                Rgb8 { r: 5, g: 8, b: 5 },
+                Rgb8 { r: 8, g: 12, b: 8 },
+                Rgb8 { r: 3, g: 4, b: 3 },
+                Rgb8 { r: 5, g: 8, b: 5 },
+                Rgb8 { r: 8, g: 12, b: 8 },
                 Rgb8 { r: 0, g: 0, b: 0 },
-                Rgb8 { r: 2, g: 3, b: 4 },
-                Rgb8 { r: 5, g: 6, b: 8 },
-                Rgb8 { r: 8, g: 10, b: 12 },
+                Rgb8 { r: 3, g: 4, b: 3 },
+                Rgb8 { r: 5, g: 8, b: 5 },
+                Rgb8 { r: 8, g: 12, b: 8 },
             ]
         );
     }
@@ -278,10 +889,291 @@ mod tests {
         assert_eq!(
             rgb,
             Rgb8 {
-                r: 72,
-                g: 76,
-                b: 88
+                r: 74,
+                g: 77,
+                b: 90
+            }
+        );
+
+        assert_eq!(decode_565_rgb(0xffff), Rgb8::MAX);
+        assert_eq!(decode_565_rgb(0x0000), Rgb8::MIN);
+    }
+
+    #[test]
+    fn encode_565() {
+        assert_eq!(encode_565_rgb(255, 255, 255), 0xffff);
+        assert_eq!(encode_565_rgb(0, 0, 0), 0x0000);
+        assert_eq!(encode_565_rgb(4, 2, 3), 0x0000);
+        assert_eq!(encode_565_rgb(5, 3, 5), 0x0821);
+
+        // Every 5:6:5 value survives a roundtrip.
+        for rgb in 0..=u16::MAX {
+            let c = decode_565_rgb(rgb);
+            assert_eq!(encode_565_rgb(c.r, c.g, c.b), rgb);
+        }
+    }
+
+    #[test]
+    fn decode_punch_through() {
+        // c0 = black, c1 = white, indices 0, 1, 2, 3 in every row.
+        let input = [0x00, 0x00, 0xff, 0xff, 0xe4, 0xe4, 0xe4, 0xe4];
+
+        let output = decode_rgba(input);
+        assert_eq!(
+            &output[..4],
+            &[
+                Rgba8::from_array([0, 0, 0, 255]),
+                Rgba8::from_array([255, 255, 255, 255]),
+                Rgba8::from_array([128, 128, 128, 255]),
+                Rgba8::from_array([0, 0, 0, 0]),
+            ]
+        );
+
+        // BC2 and BC3 always use 4 colors.
+        let output = decode_four_color(input);
+        assert_eq!(
+            output[2],
+            Rgb8 {
+                r: 85,
+                g: 85,
+                b: 85
+            }
+        );
+        assert_eq!(
+            output[3],
+            Rgb8 {
+                r: 170,
+                g: 170,
+                b: 170
+            }
+        );
+    }
+
+    #[test]
+    fn encode_opaque_order() {
+        // Opaque blocks must never be decoded with transparent texels.
+        let mut input = [Rgb8 { r: 0, g: 0, b: 0 }; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 17;
+            *texel = Rgb8 {
+                r: v,
+                g: 255 - v,
+                b: v,
+            };
+        }
+
+        let block = encode(input);
+        assert!(decode_rgba(block).iter().all(|c| c.a == 255));
+
+        let block = encode(
+            [Rgb8 {
+                r: 10,
+                g: 20,
+                b: 30,
+            }; 16],
+        );
+        assert!(decode_rgba(block).iter().all(|c| c.a == 255));
+    }
+
+    #[test]
+    fn encode_punch_through() {
+        let mut input = [Rgba8::MIN; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 17;
+            *texel = Rgba8::from_array([v, v, v, if index % 3 == 0 { 0 } else { 200 }]);
+        }
+
+        let output = decode_rgba(encode_rgba(input, 128));
+        for (lhs, rhs) in input.iter().zip(output) {
+            if lhs.a < 128 {
+                assert_eq!(rhs, Rgba8::MIN);
+            } else {
+                assert_eq!(rhs.a, 255);
+            }
+        }
+
+        // A lower threshold keeps all texels opaque.
+        let output = decode_rgba(encode_rgba(input, 0));
+        assert!(output.iter().all(|c| c.a == 255));
+
+        // Fully transparent block.
+        let output = decode_rgba(encode_rgba([Rgba8::MIN; 16], 128));
+        assert_eq!(output, [Rgba8::MIN; 16]);
+    }
+
+    #[test]
+    #[ignore = "requires test_suite/bc1_decoded.bmp written by test_suite/gen.sh"]
+    fn decode_fixture() {
+        let output = fixtures::decode(fixtures::BC1, decode);
+        let reference = fixtures::read_reference("bc1_decoded.bmp");
+
+        let mut num_texels = 0;
+        for (index, (lhs, rhs)) in output.zip(reference).enumerate() {
+            assert_eq!([lhs.r, lhs.g, lhs.b], rhs[..3], "texel {index}");
+            num_texels += 1;
+        }
+        assert_eq!(num_texels, 2560 * 1440);
+    }
+
+    #[test]
+    fn decode_fixture_close_to_bc7() {
+        // The BC1 fixture contains blocks using the 3 color mode without
+        // transparent texels.
+        let output = fixtures::decode(fixtures::BC1, decode_rgba);
+        let reference = fixtures::decode(fixtures::BC7, bc7::decode);
+
+        let mse = fixtures::mean_squared_error(output.zip(reference).map(|(lhs, rhs)| {
+            assert_eq!(lhs.a, 255);
+            (
+                [lhs.r as f32, lhs.g as f32, lhs.b as f32],
+                [rhs.r as f32, rhs.g as f32, rhs.b as f32],
+            )
+        }));
+        assert!(mse < 12.5);
+    }
+
+    #[test]
+    fn encode_fixture() {
+        let mut error = 0;
+        let mut num_texels = 0;
+        for block in fixtures::BC7.chunks(16).step_by(7) {
+            let input = bc7::decode(block.try_into().unwrap()).map(|c| Rgb8 {
+                r: c.r,
+                g: c.g,
+                b: c.b,
+            });
+            let output = decode(encode(input));
+
+            for (lhs, rhs) in input.iter().zip(output) {
+                error += lhs.distance(rhs) as u64;
+                num_texels += 1;
+            }
+        }
+
+        // Endpoints from the minimum and maximum luma gave 35.7dB.
+        let mse = error as f64 / (num_texels * 3) as f64;
+        let psnr = 10.0 * (255.0 * 255.0 / mse).log10();
+        assert!(psnr > 37.0);
+    }
+
+    #[test]
+    fn cluster_fit_punch_through() {
+        let mut input = [Rgba8::MIN; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 17;
+            *texel = Rgba8::from_array([v, 255 - v, v / 2, if index % 5 == 0 { 0 } else { 255 }]);
+        }
+
+        let output = decode_rgba(encode_rgba_cluster_fit(input, 128));
+        for (lhs, rhs) in input.iter().zip(output) {
+            assert_eq!(rhs.a == 0, lhs.a < 128);
+        }
+
+        let output = decode_rgba(encode_rgba_cluster_fit([Rgba8::MIN; 16], 128));
+        assert_eq!(output, [Rgba8::MIN; 16]);
+    }
+
+    #[test]
+    fn cluster_fit_fixture() {
+        let mut fast = 0;
+        let mut cluster = 0;
+        for block in fixtures::BC7.chunks(16).step_by(997) {
+            let input = bc7::decode(block.try_into().unwrap()).map(|c| c.to_rgb8());
+            let lhs = decode(encode(input));
+            let rhs = decode_rgba(encode_cluster_fit(input));
+
+            for ((texel, lhs), rhs) in input.iter().zip(lhs).zip(rhs) {
+                assert_eq!(rhs.a, 255);
+                fast += texel.distance(lhs) as u64;
+                cluster += texel.distance(rhs.to_rgb8()) as u64;
+            }
+        }
+
+        // Cluster fit gave about 10% less error than the fast fit.
+        assert!(cluster * 100 < fast * 95);
+    }
+
+    #[test]
+    fn encode_quality() {
+        for block in fixtures::BC7.chunks(16).step_by(997) {
+            let input = bc7::decode(block.try_into().unwrap());
+
+            let mut previous = u32::MAX;
+            for quality in [
+                Quality::Fast,
+                Quality::Normal,
+                Quality::Slow,
+                Quality::Exhaustive,
+            ] {
+                let settings = EncodeSettings {
+                    quality,
+                    ..EncodeSettings::DEFAULT
+                };
+                let output = decode(encode_with_settings(input, &settings));
+
+                // Every level starts from the result of the previous one.
+                let error = input
+                    .iter()
+                    .zip(output)
+                    .map(|(lhs, rhs)| lhs.to_rgb8().distance(rhs) as u32)
+                    .sum();
+                assert!(error <= previous);
+                previous = error;
+            }
+        }
+    }
+
+    #[test]
+    fn encode_weights() {
+        let settings = EncodeSettings {
+            weights: [0.1, 1.0, 0.1, 1.0],
+            ..EncodeSettings::DEFAULT
+        };
+
+        let mut uniform = 0;
+        let mut weighted = 0;
+        for block in fixtures::BC7.chunks(16).step_by(997) {
+            let input = bc7::decode(block.try_into().unwrap());
+            let lhs = decode(encode_with_settings(input, &EncodeSettings::DEFAULT));
+            let rhs = decode(encode_with_settings(input, &settings));
+
+            for ((texel, lhs), rhs) in input.iter().zip(lhs).zip(rhs) {
+                uniform += texel.g.abs_diff(lhs.g) as u32 * texel.g.abs_diff(lhs.g) as u32;
+                weighted += texel.g.abs_diff(rhs.g) as u32 * texel.g.abs_diff(rhs.g) as u32;
+            }
+        }
+
+        // Weighting green higher reduces its error.
+        assert!(weighted < uniform);
+    }
+
+    #[test]
+    fn encode_metric() {
+        let luma = |c: Rgb8| 0.299 * c.r as f32 + 0.587 * c.g as f32 + 0.114 * c.b as f32;
+
+        let mut errors = [0.0; 3];
+        for block in fixtures::BC7.chunks(16).step_by(997) {
+            let input = bc7::decode(block.try_into().unwrap());
+
+            for (metric, error) in [Metric::Uniform, Metric::Luma, Metric::Perceptual]
+                .into_iter()
+                .zip(&mut errors)
+            {
+                let settings = EncodeSettings {
+                    metric,
+                    ..EncodeSettings::DEFAULT
+                };
+                let output = decode(encode_with_settings(input, &settings));
+
+                for (lhs, rhs) in input.iter().zip(output) {
+                    let delta = luma(lhs.to_rgb8()) - luma(rhs);
+                    *error += delta * delta;
+                }
             }
-        )
+        }
+
+        // Both metrics favor luma over the uniform metric.
+        assert!(errors[1] < errors[0]);
+        assert!(errors[2] < errors[0]);
     }
 }
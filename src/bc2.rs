@@ -1,27 +1,26 @@
-use crate::{bc1, Block16, Rgba8};
+use crate::{bc1, Block16, BlockFormat, Decoder, EncodeSettings, Encoder, Rgba8};
 
 /// Encode 16 texels into a single BC2 block.
 pub fn encode(input: [Rgba8; 16]) -> Block16 {
-    let mut output = [0; 16];
-
-    for row in 0..4 {
-        let a0 = input[row * 4].a / 16;
-        let a1 = input[row * 4 + 1].a / 16;
-        let a2 = input[row * 4 + 2].a / 16;
-        let a3 = input[row * 4 + 3].a / 16;
+    encode_with_settings(input, &EncodeSettings::DEFAULT)
+}
 
-        debug_assert!(a0 <= 0b1111);
-        debug_assert!(a1 <= 0b1111);
-        debug_assert!(a2 <= 0b1111);
-        debug_assert!(a3 <= 0b1111);
+/// Encode 16 texels into a single BC2 block with the given settings.
+pub fn encode_with_settings(input: [Rgba8; 16], settings: &EncodeSettings) -> Block16 {
+    let mut output = [0; 16];
 
-        output[row * 2] = a0 | a1;
-        output[row * 2 + 1] = a2 | a3;
+    // Every texel stores 4 bits of alpha, starting with the low nibble.
+    let mut alpha = 0u64;
+    for (index, value) in quantize_alpha(&input, settings.dither)
+        .into_iter()
+        .enumerate()
+    {
+        alpha |= (value as u64) << (index * 4);
     }
+    output[..8].copy_from_slice(&alpha.to_le_bytes());
 
     // Color section has the same format as BC1.
-    let rgb = input.map(|c| c.to_rgb8());
-    let color_section = bc1::encode(rgb);
+    let color_section = bc1::encode_four_color(input, settings);
     output[8..].copy_from_slice(&color_section);
 
     output
@@ -31,19 +30,12 @@ pub fn encode(input: [Rgba8; 16]) -> Block16 {
 pub fn decode(input: Block16) -> [Rgba8; 16] {
     let mut output = [Rgba8::from_array([0; 4]); 16];
 
-    for row in 0..4 {
-        let a0 = (input[row * 2] >> 4) * 16;
-        let a1 = (input[row * 2] & 0b1111) * 16;
-        let a2 = (input[row * 2 + 1] >> 4) * 16;
-        let a3 = (input[row * 2 + 1] & 0b1111) * 16;
-
-        output[row * 4].a = a0;
-        output[row * 4 + 1].a = a1;
-        output[row * 4 + 2].a = a2;
-        output[row * 4 + 3].a = a3;
+    let alpha = u64::from_le_bytes(input[..8].try_into().unwrap());
+    for (index, texel) in output.iter_mut().enumerate() {
+        texel.a = decode_alpha(((alpha >> (index * 4)) & 0b1111) as u8);
     }
 
-    let colors = bc1::decode(input[8..].try_into().unwrap());
+    let colors = bc1::decode_four_color(input[8..].try_into().unwrap());
     for (i, c) in colors.into_iter().enumerate() {
         output[i].r = c.r;
         output[i].g = c.g;
@@ -52,3 +44,149 @@ pub fn decode(input: Block16) -> [Rgba8; 16] {
 
     output
 }
+
+/// Quantizes the alpha of all texels, diffusing the error to the following
+/// texels of the row and the next row if `dither` is set.
+fn quantize_alpha(input: &[Rgba8; 16], dither: bool) -> [u8; 16] {
+    let mut carry = [0i32; 16];
+    let mut output = [0; 16];
+
+    for (index, texel) in input.iter().enumerate() {
+        let target = (texel.a as i32 + carry[index]).clamp(0, 255) as u8;
+        output[index] = encode_alpha(target);
+
+        if dither {
+            // Floyd-Steinberg weights in sixteenths.
+            let error = target as i32 - decode_alpha(output[index]) as i32;
+            let (x, y) = (index % 4, index / 4);
+
+            if x < 3 {
+                carry[index + 1] += error * 7 / 16;
+            }
+            if y < 3 {
+                if x > 0 {
+                    carry[index + 3] += error * 3 / 16;
+                }
+                carry[index + 4] += error * 5 / 16;
+                if x < 3 {
+                    carry[index + 5] += error / 16;
+                }
+            }
+        }
+    }
+
+    output
+}
+
+/// Quantizes alpha to 4 bits, rounding to nearest.
+fn encode_alpha(a: u8) -> u8 {
+    ((a as u16 * 15 + 127) / 255) as u8
+}
+
+/// Expands 4-bit alpha to 8 bits.
+fn decode_alpha(a: u8) -> u8 {
+    a * 17
+}
+
+pub struct Bc2;
+
+impl BlockFormat for Bc2 {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc2 {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        out[..16].copy_from_slice(&decode(*block));
+    }
+}
+
+impl Encoder for Bc2 {
+    fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block16 {
+        let pixels: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode_with_settings(pixels, settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bc1, fixtures, EncodeSettings, Rgba8};
+
+    use super::{decode, decode_alpha, encode, encode_alpha, encode_with_settings};
+
+    #[test]
+    fn alpha_nibble_order() {
+        let mut input = [0; 16];
+        input[0] = 0x21;
+        input[7] = 0xf0;
+
+        let output = decode(input);
+        assert_eq!(output[0].a, 0x11);
+        assert_eq!(output[1].a, 0x22);
+        assert_eq!(output[14].a, 0x00);
+        assert_eq!(output[15].a, 0xff);
+    }
+
+    #[test]
+    fn alpha_quantization() {
+        assert_eq!(encode_alpha(0), 0);
+        assert_eq!(encode_alpha(8), 0);
+        assert_eq!(encode_alpha(9), 1);
+        assert_eq!(encode_alpha(247), 15);
+        assert_eq!(encode_alpha(255), 15);
+
+        for a in 0..16 {
+            assert_eq!(encode_alpha(decode_alpha(a)), a);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut input = [Rgba8::MIN; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 16;
+            *texel = Rgba8::from_array([v, v, v, 255 - v]);
+        }
+
+        let output = decode(encode(input));
+        for (lhs, rhs) in input.iter().zip(output) {
+            // Alpha is at most half a quantization step off.
+            assert!(lhs.a.abs_diff(rhs.a) <= 8);
+        }
+    }
+
+    #[test]
+    fn decode_fixture() {
+        // The color section is identical to the BC1 fixture.
+        let output = fixtures::decode(fixtures::BC2, decode);
+        let reference = fixtures::decode(fixtures::BC1, bc1::decode_four_color);
+
+        for (lhs, rhs) in output.zip(reference) {
+            assert_eq!(lhs.to_rgb8(), rhs);
+            assert_eq!(lhs.a, 255);
+        }
+    }
+
+    #[test]
+    fn alpha_dither() {
+        // Halfway between the first two levels.
+        let input = [Rgba8::from_array([0, 0, 0, 8]); 16];
+
+        let output = decode(encode(input));
+        assert!(output.iter().all(|c| c.a == 0));
+
+        let settings = EncodeSettings {
+            dither: true,
+            ..EncodeSettings::DEFAULT
+        };
+
+        // Dithering keeps the average alpha.
+        let output = decode(encode_with_settings(input, &settings));
+        let sum: u32 = output.iter().map(|c| c.a as u32).sum();
+        assert!(sum.abs_diff(16 * 8) <= 17);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{bc1, bc4, Block16, BlockFormat, Decoder, EncodeSettings, Encoder, Rgba8};
+
+/// Encode 16 texels into a single BC3 block.
+pub fn encode(input: [Rgba8; 16]) -> Block16 {
+    encode_with_settings(input, &EncodeSettings::DEFAULT)
+}
+
+/// Encode 16 texels into a single BC3 block with the given settings.
+pub fn encode_with_settings(input: [Rgba8; 16], settings: &EncodeSettings) -> Block16 {
+    let mut output = [0; 16];
+
+    // Alpha section has the same format as BC4.
+    let alpha = bc4::encode(input.map(|c| c.a));
+    output[..8].copy_from_slice(&alpha);
+
+    // Color section has the same format as BC1.
+    let color_section = bc1::encode_four_color(input, settings);
+    output[8..].copy_from_slice(&color_section);
+
+    output
+}
+
+/// Decode a single BC3 block.
+pub fn decode(input: Block16) -> [Rgba8; 16] {
+    let mut output = [Rgba8::from_array([0; 4]); 16];
+
+    let alpha = bc4::decode(input[..8].try_into().unwrap());
+    let colors = bc1::decode_four_color(input[8..].try_into().unwrap());
+
+    for (i, (c, a)) in colors.into_iter().zip(alpha).enumerate() {
+        output[i] = Rgba8::from_array([c.r, c.g, c.b, a]);
+    }
+
+    output
+}
+
+pub struct Bc3;
+
+impl BlockFormat for Bc3 {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc3 {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        out[..16].copy_from_slice(&decode(*block));
+    }
+}
+
+impl Encoder for Bc3 {
+    fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block16 {
+        let pixels: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode_with_settings(pixels, settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bc1, fixtures, Rgba8};
+
+    use super::{decode, encode};
+
+    #[test]
+    fn roundtrip() {
+        let mut input = [Rgba8::MIN; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 16;
+            *texel = Rgba8::from_array([v, v, v, 255 - v / 2]);
+        }
+
+        let output = decode(encode(input));
+        for (lhs, rhs) in input.iter().zip(output) {
+            assert!(lhs.a.abs_diff(rhs.a) <= 10);
+        }
+    }
+
+    #[test]
+    fn decode_fixture() {
+        // The color section is identical to the BC1 fixture.
+        let output = fixtures::decode(fixtures::BC3, decode);
+        let reference = fixtures::decode(fixtures::BC1, bc1::decode_four_color);
+
+        for (lhs, rhs) in output.zip(reference) {
+            assert_eq!(lhs.to_rgb8(), rhs);
+            assert_eq!(lhs.a, 255);
+        }
+    }
+}
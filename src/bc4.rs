@@ -0,0 +1,349 @@
+use crate::{Block8, BlockFormat, Decoder, EncodeSettings, Encoder, Rgba8};
+
+/// Encode 16 unsigned values into a single BC4 block.
+pub fn encode(input: [u8; 16]) -> Block8 {
+    encode_block(input.map(i16::from), Kind::Unorm)
+}
+
+/// Decode a single BC4 block into unsigned values.
+pub fn decode(input: Block8) -> [u8; 16] {
+    decode_block(input, Kind::Unorm).map(|v| v as u8)
+}
+
+/// Encode 16 signed values into a single signed BC4 block.
+///
+/// `-128` is clamped to `-127`.
+pub fn encode_snorm(input: [i8; 16]) -> Block8 {
+    encode_block(input.map(i16::from), Kind::Snorm)
+}
+
+/// Decode a single signed BC4 block.
+pub fn decode_snorm(input: Block8) -> [i8; 16] {
+    decode_block(input, Kind::Snorm).map(|v| v as i8)
+}
+
+/// The value range of a block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Unorm,
+    Snorm,
+}
+
+impl Kind {
+    const fn min(self) -> i16 {
+        match self {
+            Self::Unorm => 0,
+            Self::Snorm => -127,
+        }
+    }
+
+    const fn max(self) -> i16 {
+        match self {
+            Self::Unorm => 255,
+            Self::Snorm => 127,
+        }
+    }
+
+    fn read(self, byte: u8) -> i16 {
+        match self {
+            Self::Unorm => byte as i16,
+            // -128 and -127 both map to -1.0.
+            Self::Snorm => (byte as i8).max(-127) as i16,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Table {
+    values: [i16; 8],
+}
+
+impl Table {
+    fn new(v0: i16, v1: i16, kind: Kind) -> Self {
+        let mut values = [0; 8];
+        values[0] = v0;
+        values[1] = v1;
+
+        if v0 > v1 {
+            // 6 interpolated values.
+            for i in 1..7 {
+                values[i as usize + 1] = div_round((7 - i) * v0 + i * v1, 7);
+            }
+        } else {
+            // 4 interpolated values with explicit minimum and maximum.
+            for i in 1..5 {
+                values[i as usize + 1] = div_round((5 - i) * v0 + i * v1, 5);
+            }
+
+            values[6] = kind.min();
+            values[7] = kind.max();
+        }
+
+        Self { values }
+    }
+
+    fn get(&self, index: u8) -> i16 {
+        debug_assert!(index <= 0b111);
+        self.values[index as usize]
+    }
+
+    /// Returns the closest index and its squared error.
+    fn closest(&self, value: i16) -> (u8, u32) {
+        let mut index = 0;
+        let mut distance = u32::MAX;
+
+        for (i, v) in self.values.iter().enumerate() {
+            let delta = (value as i32 - *v as i32).unsigned_abs();
+            if delta * delta < distance {
+                index = i;
+                distance = delta * delta;
+            }
+        }
+
+        (index as u8, distance)
+    }
+}
+
+/// Divides and rounds half away from zero.
+fn div_round(n: i16, d: i16) -> i16 {
+    if n >= 0 {
+        (n + d / 2) / d
+    } else {
+        (n - d / 2) / d
+    }
+}
+
+fn encode_block(input: [i16; 16], kind: Kind) -> Block8 {
+    let input = input.map(|v| v.clamp(kind.min(), kind.max()));
+
+    let min = input.iter().copied().min().unwrap();
+    let max = input.iter().copied().max().unwrap();
+
+    // The 8 value mode spans the full range of the block.
+    let mut best = encode_with(input, max, min, kind);
+
+    // The 6 value mode has explicit minimum and maximum values, so the
+    // endpoints only need to cover the values in between.
+    let inner = input
+        .iter()
+        .copied()
+        .filter(|v| *v != kind.min() && *v != kind.max());
+    let inner_min = inner.clone().min().unwrap_or(kind.min());
+    let inner_max = inner.max().unwrap_or(kind.max());
+
+    let candidate = encode_with(input, inner_min, inner_max, kind);
+    if candidate.1 < best.1 {
+        best = candidate;
+    }
+
+    best.0
+}
+
+/// Encodes the block with the given endpoints. Returns the block and its error.
+fn encode_with(input: [i16; 16], v0: i16, v1: i16, kind: Kind) -> (Block8, u32) {
+    let table = Table::new(v0, v1, kind);
+
+    let mut indices = 0u64;
+    let mut error = 0;
+    for (i, v) in input.into_iter().enumerate() {
+        let (index, delta) = table.closest(v);
+        indices |= (index as u64) << (i * 3);
+        error += delta;
+    }
+
+    let mut output = [0; 8];
+    output[0] = v0 as u8;
+    output[1] = v1 as u8;
+    output[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+
+    (output, error)
+}
+
+fn decode_block(input: Block8, kind: Kind) -> [i16; 16] {
+    let table = Table::new(kind.read(input[0]), kind.read(input[1]), kind);
+
+    let mut bytes = [0; 8];
+    bytes[..6].copy_from_slice(&input[2..]);
+    let indices = u64::from_le_bytes(bytes);
+
+    let mut output = [0; 16];
+    for (i, v) in output.iter_mut().enumerate() {
+        *v = table.get(((indices >> (i * 3)) & 0b111) as u8);
+    }
+
+    output
+}
+
+/// Maps a signed value in `-127..=127` to `0..=255`.
+pub(crate) fn snorm_to_unorm(v: i8) -> u8 {
+    let v = v.max(-127) as i32 + 127;
+    ((v * 255 + 127) / 254) as u8
+}
+
+/// Maps an unsigned value in `0..=255` to `-127..=127`.
+pub(crate) fn unorm_to_snorm(v: u8) -> i8 {
+    ((v as i32 * 254 + 127) / 255 - 127) as i8
+}
+
+/// BC4 with unsigned values.
+///
+/// The value is stored in the red channel. Decoded texels have green and
+/// blue set to `0` and alpha set to `255`.
+pub struct Bc4;
+
+impl BlockFormat for Bc4 {
+    type Pixel = Rgba8;
+    type Block = Block8;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 8;
+}
+
+impl Decoder for Bc4 {
+    fn decode_block(block: &Block8, out: &mut [Rgba8]) {
+        for (index, r) in decode(*block).into_iter().enumerate() {
+            out[index] = Rgba8::from_array([r, 0, 0, 255]);
+        }
+    }
+}
+
+impl Encoder for Bc4 {
+    fn encode_block(input: &[Rgba8], _settings: &EncodeSettings) -> Block8 {
+        let values: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode(values.map(|c| c.r))
+    }
+}
+
+/// BC4 with signed values.
+///
+/// Signed values are mapped from `-127..=127` to `0..=255` in the red channel.
+/// Decoded texels have green and blue set to `0` and alpha set to `255`.
+pub struct Bc4Snorm;
+
+impl BlockFormat for Bc4Snorm {
+    type Pixel = Rgba8;
+    type Block = Block8;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 8;
+}
+
+impl Decoder for Bc4Snorm {
+    fn decode_block(block: &Block8, out: &mut [Rgba8]) {
+        for (index, r) in decode_snorm(*block).into_iter().enumerate() {
+            out[index] = Rgba8::from_array([snorm_to_unorm(r), 0, 0, 255]);
+        }
+    }
+}
+
+impl Encoder for Bc4Snorm {
+    fn encode_block(input: &[Rgba8], _settings: &EncodeSettings) -> Block8 {
+        let values: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode_snorm(values.map(|c| unorm_to_snorm(c.r)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bc7, fixtures};
+
+    use super::{decode, decode_snorm, encode, encode_snorm, snorm_to_unorm, unorm_to_snorm};
+
+    fn with_indices(v0: u8, v1: u8) -> [u8; 8] {
+        let mut input = [0; 8];
+        input[0] = v0;
+        input[1] = v1;
+        // Indices 0..8 for the first 8 texels.
+        let indices: u64 = (0..8).map(|i| i << (i * 3)).sum();
+        input[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        input
+    }
+
+    #[test]
+    fn decode_8() {
+        let output = decode(with_indices(255, 0));
+        assert_eq!(&output[..8], &[255, 0, 219, 182, 146, 109, 73, 36]);
+    }
+
+    #[test]
+    fn decode_6() {
+        let output = decode(with_indices(0, 200));
+        assert_eq!(&output[..8], &[0, 200, 40, 80, 120, 160, 0, 255]);
+    }
+
+    #[test]
+    fn decode_snorm_8() {
+        let output = decode_snorm(with_indices(127, -127i8 as u8));
+        assert_eq!(&output[..8], &[127, -127, 91, 54, 18, -18, -54, -91]);
+
+        // -128 is the same as -127.
+        let output = decode_snorm(with_indices(127, -128i8 as u8));
+        assert_eq!(output[1], -127);
+    }
+
+    #[test]
+    fn decode_snorm_6() {
+        let output = decode_snorm(with_indices(-100i8 as u8, 100));
+        assert_eq!(&output[..8], &[-100, 100, -60, -20, 20, 60, -127, 127]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let input = [
+            0, 255, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 130, 140,
+        ];
+
+        let output = decode(encode(input));
+        for (lhs, rhs) in input.iter().zip(output) {
+            assert!(lhs.abs_diff(rhs) <= 13);
+        }
+        assert_eq!(output[0], 0);
+        assert_eq!(output[1], 255);
+
+        let input = [77; 16];
+        assert_eq!(decode(encode(input)), input);
+    }
+
+    #[test]
+    fn roundtrip_snorm() {
+        let input = [
+            -128, 127, -50, -40, -30, -20, -10, 0, 10, 20, 30, 40, 50, 60, 70, 80,
+        ];
+
+        let output = decode_snorm(encode_snorm(input));
+        for (lhs, rhs) in input.iter().zip(output) {
+            assert!(lhs.max(&-127).abs_diff(rhs) <= 13);
+        }
+        assert_eq!(output[0], -127);
+        assert_eq!(output[1], 127);
+
+        let input = [-77; 16];
+        assert_eq!(decode_snorm(encode_snorm(input)), input);
+    }
+
+    #[test]
+    fn snorm_mapping() {
+        assert_eq!(snorm_to_unorm(-127), 0);
+        assert_eq!(snorm_to_unorm(0), 128);
+        assert_eq!(snorm_to_unorm(127), 255);
+
+        for v in -127..=127 {
+            assert_eq!(unorm_to_snorm(snorm_to_unorm(v)), v);
+        }
+    }
+
+    #[test]
+    fn decode_fixture() {
+        let output = fixtures::decode(fixtures::BC4, decode);
+        let reference = fixtures::decode(fixtures::BC7, bc7::decode);
+
+        let mse = fixtures::mean_squared_error(
+            output
+                .zip(reference)
+                .map(|(lhs, rhs)| ([lhs as f32], [rhs.r as f32])),
+        );
+        assert!(mse < 5.0);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::bc4::{snorm_to_unorm, unorm_to_snorm};
+use crate::{bc4, Block16, BlockFormat, Decoder, EncodeSettings, Encoder, Rgb8, Rgba8};
+
+/// Encode 16 unsigned red and green values into a single BC5 block.
+pub fn encode(input: [[u8; 2]; 16]) -> Block16 {
+    let mut output = [0; 16];
+
+    // Both channels are stored as separate BC4 blocks.
+    output[..8].copy_from_slice(&bc4::encode(input.map(|v| v[0])));
+    output[8..].copy_from_slice(&bc4::encode(input.map(|v| v[1])));
+
+    output
+}
+
+/// Decode a single BC5 block into unsigned red and green values.
+pub fn decode(input: Block16) -> [[u8; 2]; 16] {
+    let red = bc4::decode(input[..8].try_into().unwrap());
+    let green = bc4::decode(input[8..].try_into().unwrap());
+
+    let mut output = [[0; 2]; 16];
+    for (i, v) in output.iter_mut().enumerate() {
+        *v = [red[i], green[i]];
+    }
+
+    output
+}
+
+/// Encode 16 signed red and green values into a single signed BC5 block.
+///
+/// `-128` is clamped to `-127`.
+pub fn encode_snorm(input: [[i8; 2]; 16]) -> Block16 {
+    let mut output = [0; 16];
+
+    output[..8].copy_from_slice(&bc4::encode_snorm(input.map(|v| v[0])));
+    output[8..].copy_from_slice(&bc4::encode_snorm(input.map(|v| v[1])));
+
+    output
+}
+
+/// Decode a single signed BC5 block.
+pub fn decode_snorm(input: Block16) -> [[i8; 2]; 16] {
+    let red = bc4::decode_snorm(input[..8].try_into().unwrap());
+    let green = bc4::decode_snorm(input[8..].try_into().unwrap());
+
+    let mut output = [[0; 2]; 16];
+    for (i, v) in output.iter_mut().enumerate() {
+        *v = [red[i], green[i]];
+    }
+
+    output
+}
+
+/// Decode a single BC5 block storing a tangent-space normal map.
+///
+/// X and Y are mapped from `0..=255` to `-1.0..=1.0`. Z is reconstructed as
+/// `sqrt(1 - x² - y²)` and mapped back to `0..=255` in the blue channel.
+pub fn decode_normal(input: Block16) -> [Rgb8; 16] {
+    decode(input).map(|[x, y]| Rgb8 {
+        r: x,
+        g: y,
+        b: reconstruct_z(x, y),
+    })
+}
+
+/// Decode a single signed BC5 block storing a tangent-space normal map.
+///
+/// Z is reconstructed as `sqrt(1 - x² - y²)` and returned as the third value.
+pub fn decode_snorm_normal(input: Block16) -> [[i8; 3]; 16] {
+    decode_snorm(input).map(|[x, y]| [x, y, reconstruct_z_snorm(x, y)])
+}
+
+/// Returns the Z component of a unit vector with the given unsigned X and Y.
+pub fn reconstruct_z(x: u8, y: u8) -> u8 {
+    // Map to -255..=255.
+    let x = x as i32 * 2 - 255;
+    let y = y as i32 * 2 - 255;
+
+    let zz = (255 * 255 - x * x - y * y).max(0) as u32;
+    let z = zz.isqrt();
+
+    // Map back from 0..=255 to 128..=255.
+    ((z + 256) / 2) as u8
+}
+
+/// Returns the Z component of a unit vector with the given signed X and Y.
+pub fn reconstruct_z_snorm(x: i8, y: i8) -> i8 {
+    let x = x.max(-127) as i32;
+    let y = y.max(-127) as i32;
+
+    let zz = (127 * 127 - x * x - y * y).max(0) as u32;
+    zz.isqrt() as i8
+}
+
+/// BC5 with unsigned values.
+///
+/// Decoded texels have blue set to `0` and alpha set to `255`.
+pub struct Bc5;
+
+impl BlockFormat for Bc5 {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc5 {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        for (index, [r, g]) in decode(*block).into_iter().enumerate() {
+            out[index] = Rgba8::from_array([r, g, 0, 255]);
+        }
+    }
+}
+
+impl Encoder for Bc5 {
+    fn encode_block(input: &[Rgba8], _settings: &EncodeSettings) -> Block16 {
+        let values: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode(values.map(|c| [c.r, c.g]))
+    }
+}
+
+/// BC5 with signed values.
+///
+/// Signed values are mapped from `-127..=127` to `0..=255` in the red and
+/// green channels. Decoded texels have blue set to `0` and alpha set to `255`.
+pub struct Bc5Snorm;
+
+impl BlockFormat for Bc5Snorm {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc5Snorm {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        for (index, [r, g]) in decode_snorm(*block).into_iter().enumerate() {
+            out[index] = Rgba8::from_array([snorm_to_unorm(r), snorm_to_unorm(g), 0, 255]);
+        }
+    }
+}
+
+impl Encoder for Bc5Snorm {
+    fn encode_block(input: &[Rgba8], _settings: &EncodeSettings) -> Block16 {
+        let values: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode_snorm(values.map(|c| [unorm_to_snorm(c.r), unorm_to_snorm(c.g)]))
+    }
+}
+
+/// [`Bc5`] storing a normal map.
+///
+/// The decoder reconstructs Z in the blue channel. The encoder ignores the
+/// blue channel.
+pub struct Bc5Normal;
+
+impl BlockFormat for Bc5Normal {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc5Normal {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        for (index, c) in decode_normal(*block).into_iter().enumerate() {
+            out[index] = Rgba8::from_array([c.r, c.g, c.b, 255]);
+        }
+    }
+}
+
+impl Encoder for Bc5Normal {
+    fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block16 {
+        Bc5::encode_block(input, settings)
+    }
+}
+
+/// [`Bc5Snorm`] storing a normal map.
+///
+/// The decoder reconstructs Z in the blue channel. The encoder ignores the
+/// blue channel.
+pub struct Bc5SnormNormal;
+
+impl BlockFormat for Bc5SnormNormal {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc5SnormNormal {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        for (index, n) in decode_snorm_normal(*block).into_iter().enumerate() {
+            out[index] = Rgba8::from_array([
+                snorm_to_unorm(n[0]),
+                snorm_to_unorm(n[1]),
+                snorm_to_unorm(n[2]),
+                255,
+            ]);
+        }
+    }
+}
+
+impl Encoder for Bc5SnormNormal {
+    fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block16 {
+        Bc5Snorm::encode_block(input, settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bc4, bc7, fixtures};
+
+    use super::{decode, decode_snorm, encode, encode_snorm, reconstruct_z, reconstruct_z_snorm};
+
+    #[test]
+    fn roundtrip() {
+        let mut input = [[0; 2]; 16];
+        for (index, v) in input.iter_mut().enumerate() {
+            *v = [index as u8 * 8, 255 - index as u8 * 4];
+        }
+
+        let output = decode(encode(input));
+        for (lhs, rhs) in input.iter().zip(output) {
+            assert!(lhs[0].abs_diff(rhs[0]) <= 10);
+            assert!(lhs[1].abs_diff(rhs[1]) <= 10);
+        }
+    }
+
+    #[test]
+    fn roundtrip_snorm() {
+        let mut input = [[0; 2]; 16];
+        for (index, v) in input.iter_mut().enumerate() {
+            *v = [index as i8 * 8 - 64, 60 - index as i8 * 4];
+        }
+
+        let output = decode_snorm(encode_snorm(input));
+        for (lhs, rhs) in input.iter().zip(output) {
+            assert!(lhs[0].abs_diff(rhs[0]) <= 10);
+            assert!(lhs[1].abs_diff(rhs[1]) <= 10);
+        }
+    }
+
+    #[test]
+    fn reconstruct() {
+        // Flat normal pointing straight up.
+        assert_eq!(reconstruct_z(128, 128), 255);
+        assert_eq!(reconstruct_z_snorm(0, 0), 127);
+
+        // Normals in the tangent plane.
+        assert_eq!(reconstruct_z(255, 128), 128);
+        assert_eq!(reconstruct_z(0, 128), 128);
+        assert_eq!(reconstruct_z_snorm(127, 0), 0);
+        assert_eq!(reconstruct_z_snorm(-128, 0), 0);
+
+        // Out of range vectors are clamped.
+        assert_eq!(reconstruct_z(255, 255), 128);
+        assert_eq!(reconstruct_z_snorm(127, 127), 0);
+
+        // 45 degrees
+        assert_eq!(reconstruct_z_snorm(90, 0), 89);
+    }
+
+    #[test]
+    fn decode_fixture() {
+        let output = fixtures::decode(fixtures::BC5, decode);
+        let red = fixtures::decode(fixtures::BC4, bc4::decode);
+        let reference = fixtures::decode(fixtures::BC7, bc7::decode);
+
+        let texels = output.zip(red).zip(reference);
+        let mse = fixtures::mean_squared_error(texels.map(|((output, red), reference)| {
+            // The red channel is identical to the BC4 fixture.
+            assert_eq!(output[0], red);
+            ([output[1] as f32], [reference.g as f32])
+        }));
+        assert!(mse < 5.0);
+    }
+}
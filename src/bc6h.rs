@@ -0,0 +1,1216 @@
+use crate::bc7::{ANCHOR_INDICES_SUBSET_2_2, PARTITION_SUBSET_2, WEIGHTS_3, WEIGHTS_4};
+use crate::bits::{BitReader, BitWriter};
+use crate::half::{f16_to_f32, f32_to_f16};
+use crate::settings::ErrorMetric;
+use crate::vec3::{add, dot, scale, sub};
+use crate::{Block16, BlockFormat, Decoder, EncodeSettings, Encoder, Error, Quality, Rgb32F};
+
+use Field::{Bw, Bx, By, Bz, Gw, Gx, Gy, Gz, Rw, Rx, Ry, Rz, D};
+
+/// Decode a single unsigned (UF16) BC6H block.
+pub fn decode_uf16(input: Block16) -> [Rgb32F; 16] {
+    decode_half(input, false).map(half_to_rgb32f)
+}
+
+/// Decode a single signed (SF16) BC6H block.
+pub fn decode_sf16(input: Block16) -> [Rgb32F; 16] {
+    decode_half(input, true).map(half_to_rgb32f)
+}
+
+/// Decode a single unsigned (UF16) BC6H block, returning an error instead of a
+/// zeroed block if the block uses a reserved mode.
+pub fn try_decode_uf16(input: Block16) -> Result<[Rgb32F; 16], Error> {
+    validate_mode(input)?;
+    Ok(decode_uf16(input))
+}
+
+/// Decode a single signed (SF16) BC6H block, returning an error instead of a
+/// zeroed block if the block uses a reserved mode.
+pub fn try_decode_sf16(input: Block16) -> Result<[Rgb32F; 16], Error> {
+    validate_mode(input)?;
+    Ok(decode_sf16(input))
+}
+
+fn validate_mode(input: Block16) -> Result<(), Error> {
+    match decode_mode(&mut BitReader::new(input)) {
+        Some(_) => Ok(()),
+        None => Err(Error::UnsupportedMode),
+    }
+}
+
+fn half_to_rgb32f(texel: [u16; 3]) -> Rgb32F {
+    Rgb32F {
+        r: f16_to_f32(texel[0]),
+        g: f16_to_f32(texel[1]),
+        b: f16_to_f32(texel[2]),
+    }
+}
+
+/// Decodes a block into the bits of half-precision floats.
+fn decode_half(input: Block16, signed: bool) -> [[u16; 3]; 16] {
+    let mut reader = BitReader::new(input);
+
+    // Reserved modes must decode to zero.
+    let Some(info) = decode_mode(&mut reader) else {
+        return [[0; 3]; 16];
+    };
+
+    let mut endpoints = [[0i32; 3]; 4];
+    let mut partition = 0;
+    for &(field, msb, lsb) in info.layout {
+        let value = reader.read(msb - lsb + 1) as i32;
+
+        match field.endpoint() {
+            Some((endpoint, channel)) => endpoints[endpoint][channel] |= value << lsb,
+            None => partition |= value << lsb,
+        }
+    }
+
+    let num_endpoints = info.num_regions * 2;
+
+    if signed {
+        for value in &mut endpoints[0] {
+            *value = sign_extend(*value, info.endpoint_bits);
+        }
+    }
+
+    // All other endpoints are stored as signed deltas to the first endpoint
+    // in transformed modes.
+    if signed || info.transformed {
+        for endpoint in &mut endpoints[1..num_endpoints] {
+            for (value, bits) in endpoint.iter_mut().zip(info.delta_bits) {
+                *value = sign_extend(*value, bits);
+            }
+        }
+    }
+
+    if info.transformed {
+        let mask = (1 << info.endpoint_bits) - 1;
+
+        let base = endpoints[0];
+        for endpoint in &mut endpoints[1..num_endpoints] {
+            for (value, base) in endpoint.iter_mut().zip(base) {
+                *value = (*value + base) & mask;
+
+                if signed {
+                    *value = sign_extend(*value, info.endpoint_bits);
+                }
+            }
+        }
+    }
+
+    let endpoints = endpoints.map(|e| e.map(|v| unquantize(v, info.endpoint_bits, signed)));
+
+    let partition = partition as usize;
+
+    let mut output = [[0; 3]; 16];
+    for (index, texel) in output.iter_mut().enumerate() {
+        let (region, is_anchor) = texel_region(info, partition, index);
+
+        // The most significant bit of anchor indices is implicitly zero.
+        let bits = if is_anchor {
+            info.index_bits() - 1
+        } else {
+            info.index_bits()
+        };
+        let weight = info.weight(reader.read(bits) as usize);
+
+        let start = endpoints[region * 2];
+        let end = endpoints[region * 2 + 1];
+
+        for channel in 0..3 {
+            let value = interpolate(start[channel], end[channel], weight);
+            texel[channel] = finish_unquantize(value, signed);
+        }
+    }
+
+    output
+}
+
+/// Returns the region of a texel and whether it is the anchor of its region.
+fn texel_region(info: &ModeInfo, partition: usize, index: usize) -> (usize, bool) {
+    if info.num_regions == 2 {
+        let region = PARTITION_SUBSET_2[partition][index] as usize;
+        let anchor = match region {
+            0 => 0,
+            _ => ANCHOR_INDICES_SUBSET_2_2[partition] as usize,
+        };
+
+        (region, index == anchor)
+    } else {
+        (0, index == 0)
+    }
+}
+
+fn interpolate(start: i32, end: i32, weight: i32) -> i32 {
+    (start * (64 - weight) + end * weight + 32) >> 6
+}
+
+fn decode_mode(reader: &mut BitReader<16>) -> Option<&'static ModeInfo> {
+    // Modes with a value of 0 or 1 only use 2 bits.
+    let mut mode = reader.read(2) as u8;
+    if mode >= 2 {
+        mode |= (reader.read(3) as u8) << 2;
+    }
+
+    MODE_INFOS.iter().find(|info| info.mode == mode)
+}
+
+fn sign_extend(value: i32, bits: u8) -> i32 {
+    let shift = 32 - bits as u32;
+    (value << shift) >> shift
+}
+
+/// Expands an endpoint to 16 bits.
+fn unquantize(value: i32, bits: u8, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 {
+        value
+    } else {
+        let magnitude = value.abs();
+
+        let unq = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+
+        if value < 0 {
+            -unq
+        } else {
+            unq
+        }
+    }
+}
+
+/// Scales an interpolated value to the bits of a half-precision float.
+fn finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+/// The number of two-region partitions.
+const NUM_PARTITIONS: usize = 32;
+
+/// How many two-region partitions and refinement passes the [`Quality`]
+/// allows, and the error metric used to pick a mode.
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    /// The number of two-region partitions that are fully encoded for every
+    /// mode. All other partitions are rejected based on an estimate.
+    partitions: usize,
+    /// The number of least-squares refinement passes for every partition.
+    refine_iterations: usize,
+    metric: ErrorMetric,
+}
+
+impl Params {
+    fn new(settings: &EncodeSettings) -> Self {
+        let (partitions, refine_iterations) = match settings.quality {
+            Quality::Fast => (1, 0),
+            Quality::Normal => (4, 2),
+            Quality::Slow => (8, 4),
+            Quality::Exhaustive => (NUM_PARTITIONS, 8),
+        };
+        Self {
+            partitions,
+            refine_iterations,
+            metric: settings.error_metric(),
+        }
+    }
+}
+
+/// Encode 16 texels into a single unsigned (UF16) BC6H block.
+///
+/// Negative values are clamped to `0` and values above the largest half
+/// float are clamped to `65504.0`.
+pub fn encode_uf16(input: [Rgb32F; 16]) -> Block16 {
+    encode_uf16_with_settings(input, &EncodeSettings::DEFAULT)
+}
+
+/// Encode 16 texels into a single unsigned (UF16) BC6H block with the given
+/// settings.
+pub fn encode_uf16_with_settings(input: [Rgb32F; 16], settings: &EncodeSettings) -> Block16 {
+    let params = Params::new(settings);
+    encode_half(input.map(|c| to_interpolated(c, false)), false, &params)
+}
+
+/// Encode 16 texels into a single signed (SF16) BC6H block.
+///
+/// Values are clamped to `-65504.0..=65504.0`.
+pub fn encode_sf16(input: [Rgb32F; 16]) -> Block16 {
+    encode_sf16_with_settings(input, &EncodeSettings::DEFAULT)
+}
+
+/// Encode 16 texels into a single signed (SF16) BC6H block with the given
+/// settings.
+pub fn encode_sf16_with_settings(input: [Rgb32F; 16], settings: &EncodeSettings) -> Block16 {
+    let params = Params::new(settings);
+    encode_half(input.map(|c| to_interpolated(c, true)), true, &params)
+}
+
+/// Converts a texel to the 16-bit range in which the decoder interpolates.
+fn to_interpolated(texel: Rgb32F, signed: bool) -> [i32; 3] {
+    [texel.r, texel.g, texel.b].map(|value| {
+        // NaN is encoded as zero.
+        if value.is_nan() || (!signed && value < 0.0) {
+            return 0;
+        }
+
+        let half = f32_to_f16(value.abs()).min(0x7bff) as i32;
+
+        // Inverse of `finish_unquantize`.
+        let magnitude = if signed {
+            (half * 32 + 30) / 31
+        } else {
+            (half * 64 + 30) / 31
+        };
+
+        if value < 0.0 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    })
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Candidate {
+    error: f32,
+    block: Block16,
+}
+
+fn encode_half(texels: [[i32; 3]; 16], signed: bool, params: &Params) -> Block16 {
+    let mut best = Candidate {
+        error: f32::MAX,
+        block: [0; 16],
+    };
+
+    let all = fit_line(&texels, &[0; 16], 0);
+    for info in MODE_INFOS.iter().filter(|info| info.num_regions == 1) {
+        encode_partition(info, &texels, 0, &[all, all], signed, params, &mut best);
+    }
+
+    let candidates = select_partitions(&texels);
+    for &(partition, lines) in &candidates[..params.partitions] {
+        for info in MODE_INFOS.iter().filter(|info| info.num_regions == 2) {
+            encode_partition(info, &texels, partition, &lines, signed, params, &mut best);
+        }
+    }
+
+    best.block
+}
+
+/// Returns all two-region partitions sorted by how likely they give the best
+/// result, together with the fitted lines of both regions.
+fn select_partitions(texels: &[[i32; 3]; 16]) -> [(usize, [Line; 2]); NUM_PARTITIONS] {
+    let mut candidates = [(0.0, 0, [Line::default(); 2]); NUM_PARTITIONS];
+
+    for (partition, regions) in PARTITION_SUBSET_2[..NUM_PARTITIONS].iter().enumerate() {
+        let lines = [fit_line(texels, regions, 0), fit_line(texels, regions, 1)];
+        candidates[partition] = (lines[0].residual + lines[1].residual, partition, lines);
+    }
+
+    // Ties are broken by partition, since a stable sort requires `alloc`.
+    candidates.sort_unstable_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0).then(lhs.1.cmp(&rhs.1)));
+    candidates.map(|(_, partition, lines)| (partition, lines))
+}
+
+/// The line through a region along its principal axis.
+#[derive(Copy, Clone, Debug, Default)]
+struct Line {
+    start: [f32; 3],
+    end: [f32; 3],
+    /// The sum of squared distances of all texels to the line.
+    residual: f32,
+}
+
+/// Fits a line through all texels with the given region.
+fn fit_line(texels: &[[i32; 3]; 16], regions: &[u8; 16], region: u8) -> Line {
+    let positions = || (0..16).filter(move |&index| regions[index] == region);
+    let to_f32 = |index: usize| texels[index].map(|v| v as f32);
+
+    let mut mean = [0.0; 3];
+    let mut count = 0.0;
+    for index in positions() {
+        mean = add(mean, to_f32(index));
+        count += 1.0;
+    }
+    let mean = scale(mean, 1.0 / count);
+
+    let mut covariance = [[0.0; 3]; 3];
+    for index in positions() {
+        let offset = sub(to_f32(index), mean);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += offset[i] * offset[j];
+            }
+        }
+    }
+
+    // Power iteration, starting from the diagonal of the covariance matrix.
+    let mut axis = [covariance[0][0], covariance[1][1], covariance[2][2]];
+    for _ in 0..8 {
+        let mut next = [0.0; 3];
+        for i in 0..3 {
+            next[i] = dot(covariance[i], axis);
+        }
+
+        let norm = next.iter().fold(0.0f32, |acc, v| acc.max(v.abs()));
+        if norm == 0.0 {
+            break;
+        }
+
+        axis = scale(next, 1.0 / norm);
+    }
+
+    let length = dot(axis, axis);
+    if length == 0.0 {
+        return Line {
+            start: mean,
+            end: mean,
+            residual: 0.0,
+        };
+    }
+
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+    let mut residual = 0.0;
+    for index in positions() {
+        let offset = sub(to_f32(index), mean);
+        let t = dot(offset, axis) / length;
+
+        min = min.min(t);
+        max = max.max(t);
+        residual += dot(offset, offset) - t * t * length;
+    }
+
+    Line {
+        start: add(mean, scale(axis, min)),
+        end: add(mean, scale(axis, max)),
+        residual,
+    }
+}
+
+fn encode_partition(
+    info: &ModeInfo,
+    texels: &[[i32; 3]; 16],
+    partition: usize,
+    lines: &[Line; 2],
+    signed: bool,
+    params: &Params,
+    best: &mut Candidate,
+) {
+    let mut targets = [lines[0].start, lines[0].end, lines[1].start, lines[1].end];
+
+    for iteration in 0..=params.refine_iterations {
+        let fit = fit_endpoints(info, texels, partition, &targets, signed, &params.metric);
+
+        if fit.error < best.error {
+            *best = Candidate {
+                error: fit.error,
+                block: pack(info, partition, &fit),
+            };
+        }
+
+        if iteration != params.refine_iterations {
+            targets = least_squares(info, texels, partition, &fit, signed);
+        }
+    }
+}
+
+/// Quantized endpoints and indices of a partition.
+#[derive(Copy, Clone, Debug)]
+struct Fit {
+    /// Endpoints in the order w, x, y, z.
+    endpoints: [[i32; 3]; 4],
+    indices: [u8; 16],
+    error: f32,
+}
+
+/// Quantizes the target endpoints and selects the indices for all texels.
+fn fit_endpoints(
+    info: &ModeInfo,
+    texels: &[[i32; 3]; 16],
+    partition: usize,
+    targets: &[[f32; 3]; 4],
+    signed: bool,
+    metric: &ErrorMetric,
+) -> Fit {
+    let num_endpoints = info.num_regions * 2;
+
+    let mut endpoints = [[0; 3]; 4];
+    for (endpoint, target) in endpoints.iter_mut().zip(targets).take(num_endpoints) {
+        *endpoint = target.map(|v| quantize(v, info.endpoint_bits, signed));
+    }
+
+    // The most significant index bit of every anchor texel is implicitly
+    // zero. Swapping the endpoints inverts all indices of the region.
+    for region in 0..info.num_regions {
+        let anchor = (0..16)
+            .find(|&index| texel_region(info, partition, index) == (region, true))
+            .unwrap();
+
+        let palette = palette(info, &endpoints, region, signed);
+        let (index, _) = closest(&palette[..1 << info.index_bits()], texels[anchor], metric);
+        if index >= 1 << (info.index_bits() - 1) {
+            endpoints.swap(region * 2, region * 2 + 1);
+        }
+    }
+
+    // Deltas that do not fit are clamped, moving the endpoint towards the
+    // first endpoint.
+    if info.transformed {
+        let base = endpoints[0];
+        for endpoint in &mut endpoints[1..num_endpoints] {
+            for channel in 0..3 {
+                let limit = 1 << (info.delta_bits[channel] - 1);
+                let delta = (endpoint[channel] - base[channel]).clamp(-limit, limit - 1);
+                endpoint[channel] = base[channel] + delta;
+            }
+        }
+    }
+
+    let palettes = [
+        palette(info, &endpoints, 0, signed),
+        palette(info, &endpoints, 1, signed),
+    ];
+
+    let mut indices = [0; 16];
+    let mut error = 0.0;
+    for (index, texel) in texels.iter().enumerate() {
+        let (region, is_anchor) = texel_region(info, partition, index);
+
+        // Anchor texels can only use the lower half of the indices.
+        let num_indices = if is_anchor {
+            1 << (info.index_bits() - 1)
+        } else {
+            1 << info.index_bits()
+        };
+
+        let (value, delta) = closest(&palettes[region][..num_indices], *texel, metric);
+        indices[index] = value;
+        error += delta;
+    }
+
+    Fit {
+        endpoints,
+        indices,
+        error,
+    }
+}
+
+/// Returns the endpoints that minimize the squared error for the indices of
+/// `fit`.
+fn least_squares(
+    info: &ModeInfo,
+    texels: &[[i32; 3]; 16],
+    partition: usize,
+    fit: &Fit,
+    signed: bool,
+) -> [[f32; 3]; 4] {
+    let mut targets = fit
+        .endpoints
+        .map(|e| e.map(|v| unquantize(v, info.endpoint_bits, signed) as f32));
+
+    for region in 0..info.num_regions {
+        let mut aa = 0.0;
+        let mut ab = 0.0;
+        let mut bb = 0.0;
+        let mut ax = [0.0; 3];
+        let mut bx = [0.0; 3];
+
+        for (index, texel) in texels.iter().enumerate() {
+            if texel_region(info, partition, index).0 != region {
+                continue;
+            }
+
+            let t = info.weight(fit.indices[index] as usize) as f32 / 64.0;
+            let texel = texel.map(|v| v as f32);
+
+            aa += (1.0 - t) * (1.0 - t);
+            ab += (1.0 - t) * t;
+            bb += t * t;
+            ax = add(ax, scale(texel, 1.0 - t));
+            bx = add(bx, scale(texel, t));
+        }
+
+        // All texels use the same index.
+        let det = aa * bb - ab * ab;
+        if det == 0.0 {
+            continue;
+        }
+
+        targets[region * 2] = scale(sub(scale(ax, bb), scale(bx, ab)), 1.0 / det);
+        targets[region * 2 + 1] = scale(sub(scale(bx, aa), scale(ax, ab)), 1.0 / det);
+    }
+
+    targets
+}
+
+fn pack(info: &ModeInfo, partition: usize, fit: &Fit) -> Block16 {
+    let endpoints = &fit.endpoints;
+
+    let mut writer = BitWriter::new();
+    writer.write(info.mode as u32, info.mode_bits());
+
+    for &(field, msb, lsb) in info.layout {
+        let value = match field.endpoint() {
+            Some((0, channel)) => mask(endpoints[0][channel], info.endpoint_bits),
+            Some((endpoint, channel)) if info.transformed => mask(
+                endpoints[endpoint][channel] - endpoints[0][channel],
+                info.delta_bits[channel],
+            ),
+            Some((endpoint, channel)) => mask(endpoints[endpoint][channel], info.endpoint_bits),
+            None => partition as u32,
+        };
+
+        writer.write(value >> lsb, msb - lsb + 1);
+    }
+
+    for (index, value) in fit.indices.into_iter().enumerate() {
+        let (_, is_anchor) = texel_region(info, partition, index);
+        let bits = if is_anchor {
+            info.index_bits() - 1
+        } else {
+            info.index_bits()
+        };
+
+        writer.write(value as u32, bits);
+    }
+
+    writer.finish()
+}
+
+/// Returns the interpolated values of a region. Only the first
+/// `1 << index_bits` values are used.
+fn palette(
+    info: &ModeInfo,
+    endpoints: &[[i32; 3]; 4],
+    region: usize,
+    signed: bool,
+) -> [[i32; 3]; 16] {
+    let start = endpoints[region * 2].map(|v| unquantize(v, info.endpoint_bits, signed));
+    let end = endpoints[region * 2 + 1].map(|v| unquantize(v, info.endpoint_bits, signed));
+
+    let mut palette = [[0; 3]; 16];
+    for (index, value) in palette[..1 << info.index_bits()].iter_mut().enumerate() {
+        let weight = info.weight(index);
+        for channel in 0..3 {
+            value[channel] = interpolate(start[channel], end[channel], weight);
+        }
+    }
+
+    palette
+}
+
+/// Returns the index of the closest value and its error.
+fn closest(palette: &[[i32; 3]], texel: [i32; 3], metric: &ErrorMetric) -> (u8, f32) {
+    let mut index = 0;
+    let mut error = f32::MAX;
+
+    for (i, value) in palette.iter().enumerate() {
+        let delta = metric.color_error([0, 1, 2].map(|c| (value[c] - texel[c]) as f32));
+
+        if delta < error {
+            index = i;
+            error = delta;
+        }
+    }
+
+    (index as u8, error)
+}
+
+/// Returns the endpoint that unquantizes closest to `value`.
+fn quantize(value: f32, bits: u8, signed: bool) -> i32 {
+    let (min, max) = if signed {
+        (-(1 << (bits - 1)) + 1, (1 << (bits - 1)) - 1)
+    } else {
+        (0, (1 << bits) - 1)
+    };
+
+    let value = if value < 0.0 {
+        (value - 0.5) as i32
+    } else {
+        (value + 0.5) as i32
+    };
+
+    // Inverse of `unquantize`, which may be off by one.
+    let estimate = if signed {
+        let magnitude = ((value.unsigned_abs() as u64) << (bits - 1) >> 15) as i32;
+        magnitude * value.signum()
+    } else {
+        ((value.max(0) as u64) << bits >> 16) as i32
+    };
+    let estimate = estimate.clamp(min, max);
+
+    (estimate - 1..=estimate + 1)
+        .filter(|q| (min..=max).contains(q))
+        .min_by_key(|&q| (unquantize(q, bits, signed) - value).abs())
+        .unwrap()
+}
+
+/// Returns the lowest `bits` bits of a two's complement value.
+fn mask(value: i32, bits: u8) -> u32 {
+    (value as u32) & ((1 << bits) - 1)
+}
+
+/// BC6H with unsigned half floats.
+pub struct Bc6hUf16;
+
+impl BlockFormat for Bc6hUf16 {
+    type Pixel = Rgb32F;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc6hUf16 {
+    fn decode_block(block: &Block16, out: &mut [Rgb32F]) {
+        out[..16].copy_from_slice(&decode_uf16(*block));
+    }
+
+    fn try_decode_block(block: &Block16, out: &mut [Rgb32F]) -> Result<(), Error> {
+        out[..16].copy_from_slice(&try_decode_uf16(*block)?);
+        Ok(())
+    }
+}
+
+impl Encoder for Bc6hUf16 {
+    fn encode_block(input: &[Rgb32F], settings: &EncodeSettings) -> Block16 {
+        let pixels: [Rgb32F; 16] = input[0..16].try_into().unwrap();
+        encode_uf16_with_settings(pixels, settings)
+    }
+}
+
+/// BC6H with signed half floats.
+pub struct Bc6hSf16;
+
+impl BlockFormat for Bc6hSf16 {
+    type Pixel = Rgb32F;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc6hSf16 {
+    fn decode_block(block: &Block16, out: &mut [Rgb32F]) {
+        out[..16].copy_from_slice(&decode_sf16(*block));
+    }
+
+    fn try_decode_block(block: &Block16, out: &mut [Rgb32F]) -> Result<(), Error> {
+        out[..16].copy_from_slice(&try_decode_sf16(*block)?);
+        Ok(())
+    }
+}
+
+impl Encoder for Bc6hSf16 {
+    fn encode_block(input: &[Rgb32F], settings: &EncodeSettings) -> Block16 {
+        let pixels: [Rgb32F; 16] = input[0..16].try_into().unwrap();
+        encode_sf16_with_settings(pixels, settings)
+    }
+}
+
+/// A field in the header of a block.
+///
+/// `w` and `x` are the endpoints of the first region, `y` and `z` are the
+/// endpoints of the second region.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Rw,
+    Gw,
+    Bw,
+    Rx,
+    Gx,
+    Bx,
+    Ry,
+    Gy,
+    By,
+    Rz,
+    Gz,
+    Bz,
+    /// The partition index.
+    D,
+}
+
+impl Field {
+    /// Returns the endpoint and channel of this field, or `None` for the
+    /// partition index.
+    fn endpoint(self) -> Option<(usize, usize)> {
+        match self {
+            Self::D => None,
+            _ => Some((self as usize / 3, self as usize % 3)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ModeInfo {
+    /// Value of the mode bits.
+    mode: u8,
+    num_regions: usize,
+    /// Whether endpoints are stored as deltas to the first endpoint.
+    transformed: bool,
+    endpoint_bits: u8,
+    /// Bits of the delta values of every channel. These are the same as
+    /// the endpoint bits if the mode is not transformed.
+    delta_bits: [u8; 3],
+    /// The header layout after the mode bits as `(field, msb, lsb)` ranges.
+    layout: &'static [(Field, u8, u8)],
+}
+
+impl ModeInfo {
+    fn mode_bits(&self) -> u8 {
+        // Modes with a value of 0 or 1 only use 2 bits.
+        if self.mode < 2 {
+            2
+        } else {
+            5
+        }
+    }
+
+    fn index_bits(&self) -> u8 {
+        if self.num_regions == 2 {
+            3
+        } else {
+            4
+        }
+    }
+
+    fn weight(&self, index: usize) -> i32 {
+        match self.index_bits() {
+            3 => WEIGHTS_3[index] as i32,
+            _ => WEIGHTS_4[index] as i32,
+        }
+    }
+}
+
+const MODE_INFOS: [ModeInfo; 14] = [
+    ModeInfo {
+        mode: 0b00,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        layout: &LAYOUT_1,
+    },
+    ModeInfo {
+        mode: 0b01,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        layout: &LAYOUT_2,
+    },
+    ModeInfo {
+        mode: 0b00010,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        layout: &LAYOUT_3,
+    },
+    ModeInfo {
+        mode: 0b00110,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        layout: &LAYOUT_4,
+    },
+    ModeInfo {
+        mode: 0b01010,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        layout: &LAYOUT_5,
+    },
+    ModeInfo {
+        mode: 0b01110,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        layout: &LAYOUT_6,
+    },
+    ModeInfo {
+        mode: 0b10010,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        layout: &LAYOUT_7,
+    },
+    ModeInfo {
+        mode: 0b10110,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        layout: &LAYOUT_8,
+    },
+    ModeInfo {
+        mode: 0b11010,
+        num_regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        layout: &LAYOUT_9,
+    },
+    ModeInfo {
+        mode: 0b11110,
+        num_regions: 2,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        layout: &LAYOUT_10,
+    },
+    ModeInfo {
+        mode: 0b00011,
+        num_regions: 1,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        layout: &LAYOUT_11,
+    },
+    ModeInfo {
+        mode: 0b00111,
+        num_regions: 1,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        layout: &LAYOUT_12,
+    },
+    ModeInfo {
+        mode: 0b01011,
+        num_regions: 1,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        layout: &LAYOUT_13,
+    },
+    ModeInfo {
+        mode: 0b01111,
+        num_regions: 1,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        layout: &LAYOUT_14,
+    },
+];
+
+#[rustfmt::skip]
+const LAYOUT_1: [(Field, u8, u8); 20] = [
+    (Gy, 4, 4), (By, 4, 4), (Bz, 4, 4), (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 4, 0),
+    (Gz, 4, 4), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0), (Gz, 3, 0), (Bx, 4, 0), (Bz, 1, 1),
+    (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_2: [(Field, u8, u8); 24] = [
+    (Gy, 5, 5), (Gz, 4, 4), (Gz, 5, 5), (Rw, 6, 0), (Bz, 0, 0), (Bz, 1, 1), (By, 4, 4),
+    (Gw, 6, 0), (By, 5, 5), (Bz, 2, 2), (Gy, 4, 4), (Bw, 6, 0), (Bz, 3, 3), (Bz, 5, 5),
+    (Bz, 4, 4), (Rx, 5, 0), (Gy, 3, 0), (Gx, 5, 0), (Gz, 3, 0), (Bx, 5, 0), (By, 3, 0),
+    (Ry, 5, 0), (Rz, 5, 0), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_3: [(Field, u8, u8); 19] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 4, 0), (Rw, 10, 10), (Gy, 3, 0), (Gx, 3, 0),
+    (Gw, 10, 10), (Bz, 0, 0), (Gz, 3, 0), (Bx, 3, 0), (Bw, 10, 10), (Bz, 1, 1), (By, 3, 0),
+    (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_4: [(Field, u8, u8); 21] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 3, 0), (Rw, 10, 10), (Gz, 4, 4), (Gy, 3, 0),
+    (Gx, 4, 0), (Gw, 10, 10), (Gz, 3, 0), (Bx, 3, 0), (Bw, 10, 10), (Bz, 1, 1), (By, 3, 0),
+    (Ry, 3, 0), (Bz, 0, 0), (Bz, 2, 2), (Rz, 3, 0), (Gy, 4, 4), (Bz, 3, 3), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_5: [(Field, u8, u8); 21] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 3, 0), (Rw, 10, 10), (By, 4, 4), (Gy, 3, 0),
+    (Gx, 3, 0), (Gw, 10, 10), (Bz, 0, 0), (Gz, 3, 0), (Bx, 4, 0), (Bw, 10, 10), (By, 3, 0),
+    (Ry, 3, 0), (Bz, 1, 1), (Bz, 2, 2), (Rz, 3, 0), (Bz, 4, 4), (Bz, 3, 3), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_6: [(Field, u8, u8); 20] = [
+    (Rw, 8, 0), (By, 4, 4), (Gw, 8, 0), (Gy, 4, 4), (Bw, 8, 0), (Bz, 4, 4), (Rx, 4, 0),
+    (Gz, 4, 4), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0), (Gz, 3, 0), (Bx, 4, 0), (Bz, 1, 1),
+    (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_7: [(Field, u8, u8); 20] = [
+    (Rw, 7, 0), (Gz, 4, 4), (By, 4, 4), (Gw, 7, 0), (Bz, 2, 2), (Gy, 4, 4), (Bw, 7, 0),
+    (Bz, 3, 3), (Bz, 4, 4), (Rx, 5, 0), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0), (Gz, 3, 0),
+    (Bx, 4, 0), (Bz, 1, 1), (By, 3, 0), (Ry, 5, 0), (Rz, 5, 0), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_8: [(Field, u8, u8); 22] = [
+    (Rw, 7, 0), (Bz, 0, 0), (By, 4, 4), (Gw, 7, 0), (Gy, 5, 5), (Gy, 4, 4), (Bw, 7, 0),
+    (Gz, 5, 5), (Bz, 4, 4), (Rx, 4, 0), (Gz, 4, 4), (Gy, 3, 0), (Gx, 5, 0), (Gz, 3, 0),
+    (Bx, 4, 0), (Bz, 1, 1), (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+    (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_9: [(Field, u8, u8); 22] = [
+    (Rw, 7, 0), (Bz, 1, 1), (By, 4, 4), (Gw, 7, 0), (By, 5, 5), (Gy, 4, 4), (Bw, 7, 0),
+    (Bz, 5, 5), (Bz, 4, 4), (Rx, 4, 0), (Gz, 4, 4), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0),
+    (Gz, 3, 0), (Bx, 5, 0), (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+    (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_10: [(Field, u8, u8); 24] = [
+    (Rw, 5, 0), (Gz, 4, 4), (Bz, 0, 0), (Bz, 1, 1), (By, 4, 4), (Gw, 5, 0), (Gy, 5, 5),
+    (By, 5, 5), (Bz, 2, 2), (Gy, 4, 4), (Bw, 5, 0), (Gz, 5, 5), (Bz, 3, 3), (Bz, 5, 5),
+    (Bz, 4, 4), (Rx, 5, 0), (Gy, 3, 0), (Gx, 5, 0), (Gz, 3, 0), (Bx, 5, 0), (By, 3, 0),
+    (Ry, 5, 0), (Rz, 5, 0), (D, 4, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_11: [(Field, u8, u8); 6] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 9, 0), (Gx, 9, 0), (Bx, 9, 0),
+];
+
+#[rustfmt::skip]
+const LAYOUT_12: [(Field, u8, u8); 9] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 8, 0), (Rw, 10, 10), (Gx, 8, 0), (Gw, 10, 10),
+    (Bx, 8, 0), (Bw, 10, 10),
+];
+
+// The high endpoint bits of the following modes are stored in reverse order.
+
+#[rustfmt::skip]
+const LAYOUT_13: [(Field, u8, u8); 12] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 7, 0), (Rw, 11, 11), (Rw, 10, 10), (Gx, 7, 0),
+    (Gw, 11, 11), (Gw, 10, 10), (Bx, 7, 0), (Bw, 11, 11), (Bw, 10, 10),
+];
+
+#[rustfmt::skip]
+const LAYOUT_14: [(Field, u8, u8); 24] = [
+    (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0),
+    (Rx, 3, 0), (Rw, 15, 15), (Rw, 14, 14), (Rw, 13, 13), (Rw, 12, 12), (Rw, 11, 11), (Rw, 10, 10),
+    (Gx, 3, 0), (Gw, 15, 15), (Gw, 14, 14), (Gw, 13, 13), (Gw, 12, 12), (Gw, 11, 11), (Gw, 10, 10),
+    (Bx, 3, 0), (Bw, 15, 15), (Bw, 14, 14), (Bw, 13, 13), (Bw, 12, 12), (Bw, 11, 11), (Bw, 10, 10),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::BitWriter;
+    use crate::{bc7, fixtures, Error, Rgb32F};
+
+    use super::{
+        decode_half, decode_sf16, decode_uf16, encode_sf16, encode_uf16, try_decode_sf16,
+        try_decode_uf16, MODE_INFOS,
+    };
+
+    #[test]
+    fn layout_bits() {
+        for info in MODE_INFOS {
+            let header_bits: u8 = info.layout.iter().map(|(_, msb, lsb)| msb - lsb + 1).sum();
+
+            let expected = if info.num_regions == 2 { 82 } else { 65 };
+            assert_eq!(info.mode_bits() + header_bits, expected);
+        }
+    }
+
+    #[test]
+    fn decode_reserved() {
+        let mut writer = BitWriter::new();
+        writer.write(0b10011, 5);
+        writer.write(u32::MAX, 32);
+        writer.write(u32::MAX, 32);
+        writer.write(u32::MAX, 32);
+        writer.write(0, 27);
+        let block = writer.finish();
+        assert_eq!(decode_half(block, false), [[0; 3]; 16]);
+        assert_eq!(try_decode_uf16(block), Err(Error::UnsupportedMode));
+        assert_eq!(try_decode_sf16(block), Err(Error::UnsupportedMode));
+    }
+
+    #[test]
+    fn decode_mode11() {
+        let mut writer = BitWriter::new();
+        writer.write(0b00011, 5);
+        // w
+        writer.write(1023, 10);
+        writer.write(0, 10);
+        writer.write(512, 10);
+        // x
+        writer.write(0, 10);
+        writer.write(1023, 10);
+        writer.write(512, 10);
+        // Anchor index
+        writer.write(0, 3);
+        for _ in 0..15 {
+            writer.write(15, 4);
+        }
+        let block = writer.finish();
+
+        let output = decode_half(block, false);
+
+        assert_eq!(output[0], [0x7bff, 0, 0x3e0f]);
+        for texel in &output[1..] {
+            assert_eq!(*texel, [0, 0x7bff, 0x3e0f]);
+        }
+
+        let output = decode_uf16(block);
+        assert_eq!(
+            output[0],
+            Rgb32F {
+                r: 65504.0,
+                g: 0.0,
+                b: 1.5146484
+            }
+        );
+    }
+
+    #[test]
+    fn decode_mode11_signed() {
+        let mut writer = BitWriter::new();
+        writer.write(0b00011, 5);
+        // w: -1.0, 0.0, 1.0
+        writer.write(0x201, 10);
+        writer.write(0, 10);
+        writer.write(0x1ff, 10);
+        // x
+        writer.write(0, 10);
+        writer.write(0, 10);
+        writer.write(0, 10);
+        writer.write(0, 3);
+        for _ in 0..15 {
+            writer.write(0, 4);
+        }
+        let block = writer.finish();
+
+        let output = decode_half(block, true);
+        assert_eq!(output[0], [0xfbff, 0, 0x7bff]);
+
+        let output = decode_sf16(block);
+        assert_eq!(output[0].r, -65504.0);
+    }
+
+    #[test]
+    fn decode_fixture() {
+        // The modes with few endpoint bits are a lot less accurate for LDR
+        // content.
+        let output = fixtures::decode(fixtures::BC6H, decode_uf16);
+        let reference = fixtures::decode(fixtures::BC7, bc7::decode);
+
+        let mse = fixtures::mean_squared_error(output.zip(reference).map(|(lhs, rhs)| {
+            (
+                [lhs.r * 255.0, lhs.g * 255.0, lhs.b * 255.0],
+                [rhs.r as f32, rhs.g as f32, rhs.b as f32],
+            )
+        }));
+        assert!(mse < 10.0);
+    }
+
+    /// Returns the mean squared error of the decoded block relative to the
+    /// magnitude of the input.
+    fn relative_error(input: &[Rgb32F; 16], output: &[Rgb32F; 16]) -> f32 {
+        let mut error = 0.0;
+        for (lhs, rhs) in input.iter().zip(output) {
+            for (lhs, rhs) in [(lhs.r, rhs.r), (lhs.g, rhs.g), (lhs.b, rhs.b)] {
+                let delta = (lhs - rhs) / lhs.abs().max(1.0);
+                error += delta * delta;
+            }
+        }
+
+        error / 48.0
+    }
+
+    #[test]
+    fn encode_solid() {
+        let input = [Rgb32F {
+            r: 0.25,
+            g: 1.5,
+            b: 100.0,
+        }; 16];
+
+        let output = decode_uf16(encode_uf16(input));
+        assert!(relative_error(&input, &output) < 1e-5);
+
+        let input = input.map(|c| Rgb32F { r: -c.r, ..c });
+        let output = decode_sf16(encode_sf16(input));
+        assert!(relative_error(&input, &output) < 1e-5);
+    }
+
+    #[test]
+    fn encode_gradient() {
+        // Interpolation happens on the bits of half floats, so exponential
+        // gradients are close to linear.
+        let mut input = [Rgb32F::default(); 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = (1 << index) as f32 / 16.0;
+            *texel = Rgb32F {
+                r: v,
+                g: 0.5,
+                b: 16.0 / v,
+            };
+        }
+
+        let output = decode_uf16(encode_uf16(input));
+        assert!(relative_error(&input, &output) < 5e-3);
+    }
+
+    #[test]
+    fn encode_clamp() {
+        let input = [Rgb32F {
+            r: -1.0,
+            g: f32::NAN,
+            b: 1e6,
+        }; 16];
+
+        let output = decode_uf16(encode_uf16(input));
+        assert_eq!(
+            output[0],
+            Rgb32F {
+                r: 0.0,
+                g: 0.0,
+                b: 65504.0
+            }
+        );
+    }
+
+    #[test]
+    fn encode_fixture() {
+        let mut error = 0.0;
+        let mut num_blocks = 0;
+        for block in fixtures::BC6H.chunks(16).step_by(997) {
+            let input = decode_uf16(block.try_into().unwrap());
+            let output = decode_uf16(encode_uf16(input));
+
+            error += relative_error(&input, &output);
+            num_blocks += 1;
+        }
+
+        let mse = error / num_blocks as f32;
+        assert!(mse < 1e-5);
+    }
+}
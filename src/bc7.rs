@@ -1,277 +1,1204 @@
-use crate::bits::BitReader;
-use crate::{Block16, Rgba8};
+use core::ops::Range;
 
+use crate::bits::{BitReader, BitWriter};
+use crate::settings::ErrorMetric;
+use crate::{Block16, BlockFormat, Decoder, EncodeSettings, Encoder, Error, Quality, Rgba8};
+
+/// Decode a single BC7 block.
 pub fn decode(input: Block16) -> [Rgba8; 16] {
+    let mut reader = BitReader::new(input);
+
+    // A all-zero bit pattern is invalid. The decoder must
+    // return an zeroed block.
+    let Some(mode) = decode_mode(&mut reader) else {
+        return [Rgba8::MIN; 16];
+    };
+
+    let info = mode.info();
+
+    let partition = reader.read(info.partition_bits) as u8;
+    let rotation = reader.read(info.rotation_bits) as u8;
+    let index_selection = reader.read(info.index_selection_bits) as u8;
+
+    let endpoints = decode_endpoints(info, &mut reader);
+
+    let primary = decode_indices(&mut reader, info.num_subsets, partition, info.index_bits);
+
+    // Modes 4 and 5 store a second, unpartitioned set of indices that is
+    // used for alpha. All other modes use the same indices for all channels.
+    let (secondary, secondary_bits) = if info.secondary_index_bits != 0 {
+        let indices = decode_indices(&mut reader, 1, 0, info.secondary_index_bits);
+        (indices, info.secondary_index_bits)
+    } else {
+        (primary, info.index_bits)
+    };
+
+    // The index selection bit swaps the roles of the two index sets.
+    let (color_indices, color_bits, alpha_indices, alpha_bits) = if index_selection == 0 {
+        (primary, info.index_bits, secondary, secondary_bits)
+    } else {
+        (secondary, secondary_bits, primary, info.index_bits)
+    };
+
     let mut output = [Rgba8::MIN; 16];
+    for (index, texel) in output.iter_mut().enumerate() {
+        let subset = get_subset_index(info.num_subsets, partition, index);
+        let [start, end] = endpoints[subset as usize];
 
-    for x in 0..4 {
-        for y in 0..4 {
-            output[x as usize * 4 + y as usize] = decode_texel(input, x, y);
-        }
+        let color_index = color_indices[index];
+        let alpha_index = alpha_indices[index];
+
+        let color = Rgba8 {
+            r: interpolate(start.r, end.r, color_index, color_bits),
+            g: interpolate(start.g, end.g, color_index, color_bits),
+            b: interpolate(start.b, end.b, color_index, color_bits),
+            a: interpolate(start.a, end.a, alpha_index, alpha_bits),
+        };
+
+        *texel = rotate(color, rotation);
     }
 
     output
 }
 
-fn decode_texel(input: Block16, x: u8, y: u8) -> Rgba8 {
-    let mut reader = BitReader::new(input);
+/// Decode a single BC7 block, returning an error instead of a zeroed block if
+/// the block uses a reserved mode.
+pub fn try_decode(input: Block16) -> Result<[Rgba8; 16], Error> {
+    match decode_mode(&mut BitReader::new(input)) {
+        Some(_) => Ok(decode(input)),
+        None => Err(Error::UnsupportedMode),
+    }
+}
 
-    let mode = decode_mode(&mut reader);
+fn decode_mode(reader: &mut BitReader<16>) -> Option<Mode> {
+    // Eat bits until we find the '1' indicating the mode.
+    // If the first byte contains an all-zero bit pattern
+    // this is not a valid mode.
+    MODES.into_iter().find(|_| reader.read(1) == 1)
+}
 
-    // A all-zero bit pattern is invalid. The decoder must
-    // return an zeroed block.
-    if mode == Mode::Invalid {
-        return Rgba8::MIN;
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mode {
+    Mode0,
+    Mode1,
+    Mode2,
+    Mode3,
+    Mode4,
+    Mode5,
+    Mode6,
+    Mode7,
+}
+
+impl Mode {
+    fn info(self) -> &'static ModeInfo {
+        &MODE_INFOS[self as usize]
     }
+}
 
-    let mut subset_index = 0;
-    let mut num_subsets = 1;
-    let partition = 0;
+const MODES: [Mode; 8] = [
+    Mode::Mode0,
+    Mode::Mode1,
+    Mode::Mode2,
+    Mode::Mode3,
+    Mode::Mode4,
+    Mode::Mode5,
+    Mode::Mode6,
+    Mode::Mode7,
+];
 
-    if mode == Mode::Mode0
-        || mode == Mode::Mode1
-        || mode == Mode::Mode2
-        || mode == Mode::Mode3
-        || mode == Mode::Mode7
-    {
-        num_subsets = get_num_subsets(mode);
-        let partition = decode_partition(mode, &mut reader);
-        subset_index = get_subset_index(num_subsets, partition, x, y);
-    }
+/// The layout of a block in a specific mode.
+#[derive(Copy, Clone, Debug)]
+struct ModeInfo {
+    num_subsets: u8,
+    partition_bits: u8,
+    rotation_bits: u8,
+    index_selection_bits: u8,
+    color_bits: u8,
+    /// Zero if the mode has no alpha channel. Alpha is always 255 then.
+    alpha_bits: u8,
+    /// One P-bit for every endpoint.
+    endpoint_pbits: bool,
+    /// One P-bit for every subset, shared by both endpoints of the subset.
+    shared_pbits: bool,
+    index_bits: u8,
+    /// Zero if the mode only has a single set of indices.
+    secondary_index_bits: u8,
+}
 
-    let endpoints = decode_endpoints(mode, &mut reader);
+impl ModeInfo {
+    const fn has_pbits(&self) -> bool {
+        self.endpoint_pbits || self.shared_pbits
+    }
+}
 
-    let endpoint_start = endpoints[2 * subset_index as usize];
-    let endpoint_end = endpoints[2 * subset_index as usize];
+const MODE_INFOS: [ModeInfo; 8] = [
+    ModeInfo {
+        num_subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    ModeInfo {
+        num_subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    ModeInfo {
+        num_subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    ModeInfo {
+        num_subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    ModeInfo {
+        num_subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    ModeInfo {
+        num_subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    ModeInfo {
+        num_subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    ModeInfo {
+        num_subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
 
-    let color_index = get_color_index(mode, &mut reader, x, y, num_subsets, partition);
-    let color_bitcount = get_color_bitcount(mode);
-    // let alpha_index = todo!();
-    // let alpha_bitcount = todo!();
+fn get_subset_index(num_subsets: u8, partition: u8, index: usize) -> u8 {
+    match num_subsets {
+        // Note that the index is in y-major order.
+        2 => PARTITION_SUBSET_2[partition as usize][index],
+        3 => PARTITION_SUBSET_3[partition as usize][index],
+        _ => 0,
+    }
+}
 
-    let r = interpolate(
-        endpoint_start.r,
-        endpoint_end.r,
-        color_index,
-        color_bitcount,
-    );
-    let g = interpolate(
-        endpoint_start.g,
-        endpoint_end.g,
-        color_index,
-        color_bitcount,
-    );
-    let b = interpolate(
-        endpoint_start.b,
-        endpoint_end.b,
-        color_index,
-        color_bitcount,
-    );
-    // let a = interpolate(
-    //     endpoint_start.a,
-    //     endpoint_end.a,
-    //     alpha_index,
-    //     alpha_bitcount,
-    // );
-    let a = 255;
+/// Returns `true` if the texel at `index` is the anchor of its subset.
+///
+/// The most significant index bit of an anchor texel is implicitly zero and
+/// not stored in the block.
+fn is_anchor_index(num_subsets: u8, partition: u8, index: usize) -> bool {
+    let index = index as u8;
+    let partition = partition as usize;
 
-    Rgba8 { r, g, b, a }
+    // The first texel is always the anchor of the first subset.
+    index == 0
+        || match num_subsets {
+            2 => index == ANCHOR_INDICES_SUBSET_2_2[partition],
+            3 => {
+                index == ANCHOR_INDICES_SUBSET_3_2[partition]
+                    || index == ANCHOR_INDICES_SUBSET_3_3[partition]
+            }
+            _ => false,
+        }
 }
 
-fn decode_mode(reader: &mut BitReader<16>) -> Mode {
-    // Eat bits until we find the '1' indicating the mode.
+/// Returns the endpoints of every subset as `[start, end]` pairs.
+fn decode_endpoints(info: &ModeInfo, reader: &mut BitReader<16>) -> [[Rgba8; 2]; 3] {
+    let num_endpoints = info.num_subsets as usize * 2;
 
-    if reader.read(1) == 1 {
-        return Mode::Mode0;
+    // Channels are stored one after another for all endpoints.
+    let mut raw = [[0u8; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in raw.iter_mut().take(num_endpoints) {
+            endpoint[channel] = reader.read(info.color_bits) as u8;
+        }
     }
 
-    if reader.read(1) == 1 {
-        return Mode::Mode1;
+    if info.alpha_bits != 0 {
+        for endpoint in raw.iter_mut().take(num_endpoints) {
+            endpoint[3] = reader.read(info.alpha_bits) as u8;
+        }
     }
 
-    if reader.read(1) == 1 {
-        return Mode::Mode2;
+    let mut pbits = [0u8; 6];
+    if info.endpoint_pbits {
+        for pbit in pbits.iter_mut().take(num_endpoints) {
+            *pbit = reader.read(1) as u8;
+        }
     }
 
-    if reader.read(1) == 1 {
-        return Mode::Mode3;
+    if info.shared_pbits {
+        for subset in pbits.chunks_mut(2).take(info.num_subsets as usize) {
+            let pbit = reader.read(1) as u8;
+            subset[0] = pbit;
+            subset[1] = pbit;
+        }
     }
 
-    if reader.read(1) == 1 {
-        return Mode::Mode4;
-    }
+    let mut output = [[Rgba8::MIN; 2]; 3];
+    for index in 0..num_endpoints {
+        let endpoint = raw[index];
+        let pbit = pbits[index];
 
-    if reader.read(1) == 1 {
-        return Mode::Mode5;
+        // P-bits are appended as the LSB of every channel.
+        let unpack = |value: u8, bits: u8| {
+            if info.has_pbits() {
+                unquantize((value << 1) | pbit, bits + 1)
+            } else {
+                unquantize(value, bits)
+            }
+        };
+
+        let a = if info.alpha_bits != 0 {
+            unpack(endpoint[3], info.alpha_bits)
+        } else {
+            u8::MAX
+        };
+
+        output[index / 2][index % 2] = Rgba8 {
+            r: unpack(endpoint[0], info.color_bits),
+            g: unpack(endpoint[1], info.color_bits),
+            b: unpack(endpoint[2], info.color_bits),
+            a,
+        };
     }
 
-    if reader.read(1) == 1 {
-        return Mode::Mode6;
+    output
+}
+
+/// Expands a `bits` wide value to 8 bits by replicating the high bits into
+/// the low bits.
+fn unquantize(value: u8, bits: u8) -> u8 {
+    debug_assert!(bits <= 8);
+
+    let value = (value as u16) << (8 - bits);
+    (value | (value >> bits)) as u8
+}
+
+fn decode_indices(
+    reader: &mut BitReader<16>,
+    num_subsets: u8,
+    partition: u8,
+    bits: u8,
+) -> [u8; 16] {
+    let mut indices = [0; 16];
+
+    for (index, value) in indices.iter_mut().enumerate() {
+        let len = if is_anchor_index(num_subsets, partition, index) {
+            bits - 1
+        } else {
+            bits
+        };
+
+        *value = reader.read(len) as u8;
     }
 
-    if reader.read(1) == 1 {
-        return Mode::Mode7;
+    indices
+}
+
+fn interpolate(start: u8, end: u8, index: u8, index_bitcount: u8) -> u8 {
+    let weight = match index_bitcount {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    };
+
+    (((64 - weight) * start as u16 + weight * end as u16 + 32) >> 6) as u8
+}
+
+/// Swaps the alpha channel with a color channel.
+fn rotate(color: Rgba8, rotation: u8) -> Rgba8 {
+    let Rgba8 { r, g, b, a } = color;
+
+    match rotation {
+        1 => Rgba8 { r: a, g, b, a: r },
+        2 => Rgba8 { r, g: a, b, a: g },
+        3 => Rgba8 { r, g, b: a, a: b },
+        _ => color,
     }
+}
 
-    // The first byte contains an all-zero bit pattern.
-    // This not a valid mode.
-    Mode::Invalid
+/// The maximum number of partitions of any mode.
+const MAX_PARTITIONS: usize = 64;
+
+/// The partition and refinement budget of the [`Quality`], and the error
+/// metric and per texel error scales of one block.
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    /// The maximum number of partitions that are fully evaluated for every
+    /// multi-subset mode. All other partitions are rejected based on an
+    /// estimate.
+    partitions: usize,
+    /// The number of least-squares refinement passes for every subset fit.
+    refine_iterations: usize,
+    /// The error of a texel difference `d` is `d^T * metric * d`.
+    metric: [[f32; 4]; 4],
+    /// Scale of the error of every texel and channel.
+    texel_scales: [[f32; 4]; 16],
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum Mode {
-    /// An invalid all zero mode.
-    ///
-    /// The decoder must return an all-zero block.
-    Invalid,
-    Mode0,
-    Mode1,
-    Mode2,
-    Mode3,
-    Mode4,
-    Mode5,
-    Mode6,
-    Mode7,
+impl Params {
+    fn new(settings: &EncodeSettings, input: &[Rgba8; 16]) -> Self {
+        let (partitions, refine_iterations) = match settings.quality {
+            Quality::Fast => (1, 0),
+            Quality::Normal => (4, 2),
+            Quality::Slow => (16, 4),
+            Quality::Exhaustive => (MAX_PARTITIONS, 8),
+        };
+
+        // Alpha itself is never scaled.
+        let texel_scales = input.map(|c| {
+            let scale = settings.alpha_weight(c.a);
+            [scale, scale, scale, 1.0]
+        });
+
+        Self {
+            partitions,
+            refine_iterations,
+            metric: metric_matrix(&settings.error_metric()),
+            texel_scales,
+        }
+    }
+}
+
+/// Encode 16 texels into a single BC7 block.
+///
+/// All eight modes are tried and the block with the lowest error is returned.
+pub fn encode(input: [Rgba8; 16]) -> Block16 {
+    encode_with_settings(input, &EncodeSettings::DEFAULT)
 }
 
-fn get_num_subsets(mode: Mode) -> u8 {
-    match mode {
-        Mode::Mode0 => 3,
-        Mode::Mode1 => 2,
-        Mode::Mode2 => 3,
-        Mode::Mode3 => 2,
-        Mode::Mode4 => 1,
-        Mode::Mode5 => 1,
-        Mode::Mode6 => 1,
-        Mode::Mode7 => 2,
-        Mode::Invalid => unreachable!(),
+/// Encode 16 texels into a single BC7 block with the given settings.
+pub fn encode_with_settings(input: [Rgba8; 16], settings: &EncodeSettings) -> Block16 {
+    let texels = input.map(|c| [c.r, c.g, c.b, c.a]);
+    let params = Params::new(settings, &input);
+
+    let mut best = Candidate {
+        error: f32::MAX,
+        block: [0; 16],
+    };
+
+    for mode in MODES {
+        encode_mode(mode, &texels, &params, &mut best);
     }
+
+    best.block
 }
 
-fn decode_partition(mode: Mode, reader: &mut BitReader<16>) -> u8 {
-    match mode {
-        Mode::Mode0 => reader.read(4) as u8,
-        _ => todo!(),
+#[derive(Copy, Clone, Debug)]
+struct Candidate {
+    error: f32,
+    block: Block16,
+}
+
+fn encode_mode(mode: Mode, texels: &[[u8; 4]; 16], params: &Params, best: &mut Candidate) {
+    let info = mode.info();
+
+    for rotation in 0..1 << info.rotation_bits {
+        // Rotation is its own inverse, so we rotate the input and let the
+        // decoder undo it. The metric and scales follow the channels.
+        let texels = texels.map(|texel| rotate_channels(texel, rotation));
+        let params = Params {
+            metric: rotate_channels(
+                params.metric.map(|row| rotate_channels(row, rotation)),
+                rotation,
+            ),
+            texel_scales: params
+                .texel_scales
+                .map(|scale| rotate_channels(scale, rotation)),
+            ..*params
+        };
+
+        let (partitions, num_partitions) = select_partitions(info, &texels, params.partitions);
+
+        for index_selection in 0..1 << info.index_selection_bits {
+            for &partition in &partitions[..num_partitions] {
+                let candidate = encode_partition(
+                    mode,
+                    &texels,
+                    partition,
+                    rotation,
+                    index_selection,
+                    &params,
+                    best.error,
+                );
+
+                if let Some(candidate) = candidate {
+                    *best = candidate;
+                }
+            }
+        }
     }
 }
 
-fn get_subset_index(num_subsets: u8, partition_index: u8, x: u8, y: u8) -> u8 {
-    match num_subsets {
-        // Note that the index is in y-major order.
-        2 => PARTITION_SUBSET_2[partition_index as usize][x as usize + y as usize * 4],
-        3 => PARTITION_SUBSET_3[partition_index as usize][x as usize + y as usize * 4],
-        _ => todo!(),
+fn rotate_channels<T>(mut texel: [T; 4], rotation: u8) -> [T; 4] {
+    if rotation != 0 {
+        texel.swap(rotation as usize - 1, 3);
     }
+
+    texel
 }
 
-fn decode_endpoints(mode: Mode, reader: &mut BitReader<16>) -> [Rgba8; 6] {
-    let mut output = [Rgba8::MIN; 6];
+/// Returns up to `max_candidates` partitions most likely to give the best
+/// result for `info`.
+fn select_partitions(
+    info: &ModeInfo,
+    texels: &[[u8; 4]; 16],
+    max_candidates: usize,
+) -> ([u8; MAX_PARTITIONS], usize) {
+    let mut candidates = [(f32::MAX, 0); MAX_PARTITIONS];
+    let max_candidates = max_candidates.clamp(1, MAX_PARTITIONS);
+
+    if info.num_subsets == 1 {
+        return ([0; MAX_PARTITIONS], 1);
+    }
+
+    let channels = if info.alpha_bits != 0 { 4 } else { 3 };
 
-    match mode {
-        Mode::Mode0 => {
-            for index in 0..6 {
-                output[index].r = reader.read(4) as u8;
+    for partition in 0..1u8 << info.partition_bits {
+        let mut estimate = 0.0;
+        for subset in 0..info.num_subsets {
+            let (positions, len) = subset_positions(info.num_subsets, partition, subset);
+            estimate += line_residual(texels, &positions[..len], 0..channels);
+        }
+
+        // Keep the list sorted with the best candidate first.
+        if estimate < candidates[max_candidates - 1].0 {
+            let mut index = max_candidates - 1;
+            while index > 0 && candidates[index - 1].0 > estimate {
+                candidates[index] = candidates[index - 1];
+                index -= 1;
             }
 
-            for index in 0..6 {
-                output[index].g = reader.read(4) as u8;
+            candidates[index] = (estimate, partition);
+        }
+    }
+
+    let num_partitions = candidates.iter().filter(|(e, _)| *e != f32::MAX).count();
+    (candidates.map(|(_, partition)| partition), num_partitions)
+}
+
+/// Returns the positions of all texels in `subset`.
+fn subset_positions(num_subsets: u8, partition: u8, subset: u8) -> ([usize; 16], usize) {
+    let mut positions = [0; 16];
+    let mut len = 0;
+
+    for index in 0..16 {
+        if get_subset_index(num_subsets, partition, index) == subset {
+            positions[len] = index;
+            len += 1;
+        }
+    }
+
+    (positions, len)
+}
+
+/// Returns the anchor texel of `subset`.
+fn anchor_index(num_subsets: u8, partition: u8, subset: u8) -> usize {
+    let partition = partition as usize;
+
+    let index = match (num_subsets, subset) {
+        (2, 1) => ANCHOR_INDICES_SUBSET_2_2[partition],
+        (3, 1) => ANCHOR_INDICES_SUBSET_3_2[partition],
+        (3, 2) => ANCHOR_INDICES_SUBSET_3_3[partition],
+        _ => 0,
+    };
+
+    index as usize
+}
+
+fn encode_partition(
+    mode: Mode,
+    texels: &[[u8; 4]; 16],
+    partition: u8,
+    rotation: u8,
+    index_selection: u8,
+    params: &Params,
+    max_error: f32,
+) -> Option<Candidate> {
+    let info = mode.info();
+
+    let mut error = 0.0;
+
+    // Modes without alpha always decode to an opaque alpha.
+    if info.alpha_bits == 0 {
+        for texel in texels {
+            let delta = (255 - texel[3]) as f32;
+            error += delta * delta * params.metric[3][3];
+        }
+    }
+
+    let (color_index_bits, alpha_index_bits) = match (info.secondary_index_bits, index_selection) {
+        (0, _) => (info.index_bits, info.index_bits),
+        (bits, 0) => (info.index_bits, bits),
+        (bits, _) => (bits, info.index_bits),
+    };
+
+    let pbits = if info.endpoint_pbits {
+        PBits::Unique
+    } else if info.shared_pbits {
+        PBits::Shared
+    } else {
+        PBits::None
+    };
+
+    let mut endpoints = [[[0; 4]; 2]; 3];
+    let mut endpoint_pbits = [[0; 2]; 3];
+    let mut color_indices = [0; 16];
+    let mut alpha_indices = [0; 16];
+
+    for subset in 0..info.num_subsets {
+        let (positions, len) = subset_positions(info.num_subsets, partition, subset);
+        let positions = &positions[..len];
+        let anchor = anchor_index(info.num_subsets, partition, subset);
+        let subset = subset as usize;
+
+        if info.secondary_index_bits != 0 {
+            // Color and alpha are fitted independently with their own indices.
+            let color = fit_subset(
+                texels,
+                positions,
+                anchor,
+                &FitParams {
+                    channels: 0..3,
+                    bits: info.color_bits,
+                    pbits,
+                    index_bits: color_index_bits,
+                    metric: params.metric,
+                    texel_scales: params.texel_scales,
+                    refine_iterations: params.refine_iterations,
+                },
+            );
+            let alpha = fit_subset(
+                texels,
+                positions,
+                anchor,
+                &FitParams {
+                    channels: 3..4,
+                    bits: info.alpha_bits,
+                    pbits,
+                    index_bits: alpha_index_bits,
+                    metric: params.metric,
+                    texel_scales: params.texel_scales,
+                    refine_iterations: params.refine_iterations,
+                },
+            );
+
+            error += color.error + alpha.error;
+
+            for (endpoint, (color, alpha)) in endpoints[subset]
+                .iter_mut()
+                .zip(color.endpoints.iter().zip(alpha.endpoints))
+            {
+                *endpoint = [color[0], color[1], color[2], alpha[3]];
             }
 
-            for index in 0..6 {
-                output[index].b = reader.read(4) as u8;
+            for &index in positions {
+                color_indices[index] = color.indices[index];
+                alpha_indices[index] = alpha.indices[index];
             }
+        } else {
+            let channels = if info.alpha_bits != 0 { 0..4 } else { 0..3 };
+            debug_assert!(info.alpha_bits == 0 || info.alpha_bits == info.color_bits);
 
-            // P-bits as LSB
-            for color in &mut output {
-                let p = (reader.read(1) as u8) << 3;
+            let fit = fit_subset(
+                texels,
+                positions,
+                anchor,
+                &FitParams {
+                    channels,
+                    bits: info.color_bits,
+                    pbits,
+                    index_bits: info.index_bits,
+                    metric: params.metric,
+                    texel_scales: params.texel_scales,
+                    refine_iterations: params.refine_iterations,
+                },
+            );
 
-                // Duplicate the color bits for the lower bits after the P-bit.
-                let low_r = color.r >> 1;
-                let low_g = color.g >> 1;
-                let low_b = color.b >> 1;
+            error += fit.error;
+            endpoints[subset] = fit.endpoints;
+            endpoint_pbits[subset] = fit.pbits;
 
-                color.r = (color.r << 4) | p | low_r;
-                color.g = (color.g << 4) | p | low_g;
-                color.b = (color.b << 4) | p | low_b;
+            for &index in positions {
+                color_indices[index] = fit.indices[index];
             }
         }
-        _ => todo!(),
+
+        if error >= max_error {
+            return None;
+        }
     }
 
-    output
+    let mut writer = BitWriter::<16>::new();
+    writer.write(1 << mode as u32, mode as u8 + 1);
+    writer.write(partition as u32, info.partition_bits);
+    writer.write(rotation as u32, info.rotation_bits);
+    writer.write(index_selection as u32, info.index_selection_bits);
+
+    let num_subsets = info.num_subsets as usize;
+    let endpoints = &endpoints[..num_subsets];
+
+    for channel in 0..3 {
+        for endpoint in endpoints.iter().flatten() {
+            writer.write(endpoint[channel] as u32, info.color_bits);
+        }
+    }
+
+    if info.alpha_bits != 0 {
+        for endpoint in endpoints.iter().flatten() {
+            writer.write(endpoint[3] as u32, info.alpha_bits);
+        }
+    }
+
+    if info.endpoint_pbits {
+        for pbit in endpoint_pbits[..num_subsets].iter().flatten() {
+            writer.write(*pbit as u32, 1);
+        }
+    }
+
+    if info.shared_pbits {
+        for pbits in &endpoint_pbits[..num_subsets] {
+            writer.write(pbits[0] as u32, 1);
+        }
+    }
+
+    let (primary, secondary) = if index_selection == 0 {
+        (color_indices, alpha_indices)
+    } else {
+        (alpha_indices, color_indices)
+    };
+
+    encode_indices(
+        &mut writer,
+        &primary,
+        info.num_subsets,
+        partition,
+        info.index_bits,
+    );
+    if info.secondary_index_bits != 0 {
+        encode_indices(&mut writer, &secondary, 1, 0, info.secondary_index_bits);
+    }
+
+    Some(Candidate {
+        error,
+        block: writer.finish(),
+    })
 }
 
-fn get_color_index(
-    mode: Mode,
-    reader: &mut BitReader<16>,
-    x: u8,
-    y: u8,
+fn encode_indices(
+    writer: &mut BitWriter<16>,
+    indices: &[u8; 16],
     num_subsets: u8,
     partition: u8,
-) -> u8 {
-    let partition_index = (x as usize + y as usize * 4) as u8;
-
-    let read_len = match num_subsets {
-        2 => {
-            if partition_index == ANCHOR_INDICES_SUBSET_0[partition as usize] {
-                2 - 1
-            } else if partition_index == ANCHOR_INDICES_SUBSET_2_2[partition as usize] {
-                2 - 1
-            } else {
-                2
+    bits: u8,
+) {
+    for (index, value) in indices.iter().enumerate() {
+        let len = if is_anchor_index(num_subsets, partition, index) {
+            debug_assert!(*value < 1 << (bits - 1));
+            bits - 1
+        } else {
+            bits
+        };
+
+        writer.write(*value as u32, len);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PBits {
+    None,
+    /// A separate P-bit for both endpoints.
+    Unique,
+    /// A single P-bit shared by both endpoints.
+    Shared,
+}
+
+impl PBits {
+    /// Returns all P-bit combinations for the endpoints.
+    fn combinations(self) -> &'static [[u8; 2]] {
+        match self {
+            Self::None => &[[0, 0]],
+            Self::Unique => &[[0, 0], [0, 1], [1, 0], [1, 1]],
+            Self::Shared => &[[0, 0], [1, 1]],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FitParams {
+    channels: Range<usize>,
+    /// Bits per endpoint channel, not including the P-bit.
+    bits: u8,
+    pbits: PBits,
+    index_bits: u8,
+    metric: [[f32; 4]; 4],
+    texel_scales: [[f32; 4]; 16],
+    refine_iterations: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct SubsetFit {
+    /// Quantized endpoints without P-bits.
+    endpoints: [[u8; 4]; 2],
+    pbits: [u8; 2],
+    /// Indices of all texels. Only texels of the fitted subset are valid.
+    indices: [u8; 16],
+    error: f32,
+}
+
+/// Fits a pair of endpoints and indices to the texels at `positions` for the
+/// channels given in `params`.
+///
+/// The endpoints are swapped if necessary so the most significant index bit
+/// of the `anchor` texel is zero.
+fn fit_subset(
+    texels: &[[u8; 4]; 16],
+    positions: &[usize],
+    anchor: usize,
+    params: &FitParams,
+) -> SubsetFit {
+    let (mean, axis) = principal_axis(texels, positions, params.channels.clone());
+
+    // Use the extent of the texels along the principal axis as
+    // initial endpoints.
+    let axis_len = dot(axis, axis, params.channels.clone());
+    let mut min = 0.0f32;
+    let mut max = 0.0f32;
+    if axis_len > 0.0 {
+        for &index in positions {
+            let offset = sub(to_f32(texels[index]), mean);
+            let t = dot(offset, axis, params.channels.clone()) / axis_len;
+            min = min.min(t);
+            max = max.max(t);
+        }
+    }
+
+    let start = add(mean, scale(axis, min));
+    let end = add(mean, scale(axis, max));
+
+    let mut best = evaluate_endpoints(texels, positions, [start, end], params);
+
+    for _ in 0..params.refine_iterations {
+        let Some(endpoints) = least_squares(texels, positions, &best.indices, params) else {
+            break;
+        };
+
+        let fit = evaluate_endpoints(texels, positions, endpoints, params);
+        if fit.error >= best.error {
+            break;
+        }
+
+        best = fit;
+    }
+
+    let max_index = (1 << params.index_bits) - 1;
+    if best.indices[anchor] > max_index >> 1 {
+        best.endpoints.swap(0, 1);
+        best.pbits.swap(0, 1);
+        for &index in positions {
+            best.indices[index] = max_index - best.indices[index];
+        }
+    }
+
+    best
+}
+
+/// Quantizes the endpoints and picks the best index for every texel. All
+/// P-bit combinations are tried.
+fn evaluate_endpoints(
+    texels: &[[u8; 4]; 16],
+    positions: &[usize],
+    endpoints: [[f32; 4]; 2],
+    params: &FitParams,
+) -> SubsetFit {
+    let mut best = SubsetFit {
+        endpoints: [[0; 4]; 2],
+        pbits: [0; 2],
+        indices: [0; 16],
+        error: f32::MAX,
+    };
+
+    for &pbits in params.pbits.combinations() {
+        let mut fit = SubsetFit {
+            endpoints: [[0; 4]; 2],
+            pbits,
+            indices: [0; 16],
+            error: 0.0,
+        };
+
+        let mut decoded = [[0; 4]; 2];
+        for endpoint in 0..2 {
+            let pbit = (params.pbits != PBits::None).then_some(pbits[endpoint]);
+
+            for channel in params.channels.clone() {
+                let value = quantize(endpoints[endpoint][channel], params.bits, pbit);
+                fit.endpoints[endpoint][channel] = value;
+                decoded[endpoint][channel] = dequantize(value, params.bits, pbit);
             }
         }
-        3 => {
-            if partition_index == ANCHOR_INDICES_SUBSET_0[partition as usize] {
-                3 - 1
-            } else if partition_index == ANCHOR_INDICES_SUBSET_3_2[partition as usize] {
-                3 - 1
-            } else if partition_index == ANCHOR_INDICES_SUBSET_3_3[partition as usize] {
-                3 - 1
-            } else {
-                3
+
+        let num_indices = 1 << params.index_bits;
+        let mut palette = [[0; 4]; 16];
+        for (index, color) in palette.iter_mut().enumerate().take(num_indices) {
+            for channel in params.channels.clone() {
+                color[channel] = interpolate(
+                    decoded[0][channel],
+                    decoded[1][channel],
+                    index as u8,
+                    params.index_bits,
+                );
             }
         }
-        _ => todo!(),
+
+        for &position in positions {
+            let texel = texels[position];
+
+            let mut best_index = 0;
+            let mut best_error = f32::MAX;
+            for (index, color) in palette.iter().enumerate().take(num_indices) {
+                let error = distance(
+                    texel,
+                    *color,
+                    params.channels.clone(),
+                    &params.metric,
+                    params.texel_scales[position],
+                );
+                if error < best_error {
+                    best_index = index;
+                    best_error = error;
+                }
+            }
+
+            fit.indices[position] = best_index as u8;
+            fit.error += best_error;
+        }
+
+        if fit.error < best.error {
+            best = fit;
+        }
+    }
+
+    best
+}
+
+/// Solves for the endpoints that minimize the error for the given indices.
+///
+/// Returns `None` if all texels use the same weight.
+fn least_squares(
+    texels: &[[u8; 4]; 16],
+    positions: &[usize],
+    indices: &[u8; 16],
+    params: &FitParams,
+) -> Option<[[f32; 4]; 2]> {
+    let weights: &[u16] = match params.index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    };
+
+    // Every channel is solved separately since the texels may scale them
+    // differently.
+    let mut aa = [0.0; 4];
+    let mut ab = [0.0; 4];
+    let mut bb = [0.0; 4];
+    let mut ax = [0.0; 4];
+    let mut bx = [0.0; 4];
+
+    for &position in positions {
+        let w = weights[indices[position] as usize] as f32 / 64.0;
+        let texel = to_f32(texels[position]);
+        let scale = params.texel_scales[position];
+
+        for channel in params.channels.clone() {
+            let s = scale[channel];
+            aa[channel] += s * (1.0 - w) * (1.0 - w);
+            ab[channel] += s * (1.0 - w) * w;
+            bb[channel] += s * w * w;
+            ax[channel] += s * (1.0 - w) * texel[channel];
+            bx[channel] += s * w * texel[channel];
+        }
+    }
+
+    let mut endpoints = [[0.0; 4]; 2];
+    for c in params.channels.clone() {
+        let det = aa[c] * bb[c] - ab[c] * ab[c];
+        if det.abs() <= f32::EPSILON * aa[c] * bb[c] {
+            return None;
+        }
+
+        endpoints[0][c] = (bb[c] * ax[c] - ab[c] * bx[c]) / det;
+        endpoints[1][c] = (aa[c] * bx[c] - ab[c] * ax[c]) / det;
+    }
+
+    Some(endpoints)
+}
+
+/// Returns the stored value with `bits` bits that decodes closest to `value`.
+fn quantize(value: f32, bits: u8, pbit: Option<u8>) -> u8 {
+    let max = (1 << bits) - 1;
+    let value = value.clamp(0.0, 255.0);
+
+    let estimate = match pbit {
+        Some(pbit) => {
+            let levels = ((1 << (bits + 1)) - 1) as f32;
+            (value / 255.0 * levels - pbit as f32) / 2.0
+        }
+        None => value / 255.0 * max as f32,
     };
 
-    reader.read(read_len) as u8
+    // The estimate may be off by one because of the bit replication
+    // in the decoder.
+    let estimate = (estimate + 0.5) as i32;
+
+    let mut best = 0;
+    let mut best_error = f32::MAX;
+    for candidate in estimate - 1..=estimate + 1 {
+        let candidate = candidate.clamp(0, max) as u8;
+        let error = (dequantize(candidate, bits, pbit) as f32 - value).abs();
+        if error < best_error {
+            best = candidate;
+            best_error = error;
+        }
+    }
+
+    best
 }
 
-fn get_color_bitcount(mode: Mode) -> u8 {
-    match mode {
-        Mode::Mode0 => 3,
-        _ => todo!(),
+fn dequantize(value: u8, bits: u8, pbit: Option<u8>) -> u8 {
+    match pbit {
+        Some(pbit) => unquantize((value << 1) | pbit, bits + 1),
+        None => unquantize(value, bits),
     }
 }
 
-fn interpolate(start: u8, end: u8, index: u8, index_bitcount: u8) -> u8 {
-    match index_bitcount {
-        2 => {
-            (((64 - WEIGHTS_2[index as usize]) * start as u16
-                + WEIGHTS_2[index as usize] * end as u16
-                + 32)
-                >> 6) as u8
+/// Returns the mean and the principal axis of the texels at `positions`.
+///
+/// The axis is not normalized and is zero if all texels are equal.
+fn principal_axis(
+    texels: &[[u8; 4]; 16],
+    positions: &[usize],
+    channels: Range<usize>,
+) -> ([f32; 4], [f32; 4]) {
+    let mut mean = [0.0; 4];
+    for &index in positions {
+        mean = add(mean, to_f32(texels[index]));
+    }
+    let mean = scale(mean, 1.0 / positions.len() as f32);
+
+    let mut covariance = [[0.0; 4]; 4];
+    for &index in positions {
+        let offset = sub(to_f32(texels[index]), mean);
+        for i in channels.clone() {
+            for j in channels.clone() {
+                covariance[i][j] += offset[i] * offset[j];
+            }
+        }
+    }
+
+    // Power iteration, starting from the diagonal of the covariance matrix.
+    let mut axis = [0.0; 4];
+    for channel in channels.clone() {
+        axis[channel] = covariance[channel][channel];
+    }
+
+    for _ in 0..8 {
+        let mut next = [0.0; 4];
+        for i in channels.clone() {
+            for j in channels.clone() {
+                next[i] += covariance[i][j] * axis[j];
+            }
+        }
+
+        let norm = next.iter().fold(0.0f32, |acc, v| acc.max(v.abs()));
+        if norm == 0.0 {
+            break;
         }
-        3 => {
-            (((64 - WEIGHTS_3[index as usize]) * start as u16
-                + WEIGHTS_3[index as usize] * end as u16)
-                >> 6) as u8
+
+        axis = scale(next, 1.0 / norm);
+    }
+
+    (mean, axis)
+}
+
+/// Returns the squared distance of the texels at `positions` to their
+/// principal axis.
+fn line_residual(texels: &[[u8; 4]; 16], positions: &[usize], channels: Range<usize>) -> f32 {
+    let (mean, axis) = principal_axis(texels, positions, channels.clone());
+    let axis_len = dot(axis, axis, channels.clone());
+
+    let mut residual = 0.0;
+    for &index in positions {
+        let offset = sub(to_f32(texels[index]), mean);
+        let len = dot(offset, offset, channels.clone());
+
+        if axis_len > 0.0 {
+            let t = dot(offset, axis, channels.clone());
+            residual += len - t * t / axis_len;
+        } else {
+            residual += len;
         }
-        4 => {
-            (((64 - WEIGHTS_4[index as usize]) * start as u16
-                + WEIGHTS_4[index as usize] * end as u16)
-                >> 6) as u8
+    }
+
+    residual
+}
+
+/// Returns the error of two texels, only considering `channels`.
+///
+/// The metric only relates channels with the same scale, so the scale of
+/// either channel applies.
+fn distance(
+    lhs: [u8; 4],
+    rhs: [u8; 4],
+    channels: Range<usize>,
+    metric: &[[f32; 4]; 4],
+    scale: [f32; 4],
+) -> f32 {
+    let delta = [0, 1, 2, 3].map(|channel| lhs[channel] as f32 - rhs[channel] as f32);
+
+    let mut acc = 0.0;
+    for i in channels.clone() {
+        for j in channels.clone() {
+            acc += delta[i] * delta[j] * metric[i][j] * scale[i];
         }
-        _ => unreachable!(),
+    }
+
+    acc
+}
+
+/// Returns the metric as a matrix over all four channels.
+fn metric_matrix(metric: &ErrorMetric) -> [[f32; 4]; 4] {
+    let mut matrix = [[0.0; 4]; 4];
+    for (row, color) in matrix.iter_mut().zip(metric.color) {
+        row[..3].copy_from_slice(&color);
+    }
+    matrix[3][3] = metric.alpha;
+
+    matrix
+}
+
+fn to_f32(texel: [u8; 4]) -> [f32; 4] {
+    texel.map(|v| v as f32)
+}
+
+fn add(lhs: [f32; 4], rhs: [f32; 4]) -> [f32; 4] {
+    [
+        lhs[0] + rhs[0],
+        lhs[1] + rhs[1],
+        lhs[2] + rhs[2],
+        lhs[3] + rhs[3],
+    ]
+}
+
+fn sub(lhs: [f32; 4], rhs: [f32; 4]) -> [f32; 4] {
+    [
+        lhs[0] - rhs[0],
+        lhs[1] - rhs[1],
+        lhs[2] - rhs[2],
+        lhs[3] - rhs[3],
+    ]
+}
+
+fn scale(v: [f32; 4], s: f32) -> [f32; 4] {
+    v.map(|v| v * s)
+}
+
+fn dot(lhs: [f32; 4], rhs: [f32; 4], channels: Range<usize>) -> f32 {
+    channels.map(|channel| lhs[channel] * rhs[channel]).sum()
+}
+
+pub struct Bc7;
+
+impl BlockFormat for Bc7 {
+    type Pixel = Rgba8;
+    type Block = Block16;
+
+    const BLOCK_WIDTH: usize = 4;
+    const BLOCK_HEIGHT: usize = 4;
+    const BLOCK_SIZE: usize = 16;
+}
+
+impl Decoder for Bc7 {
+    fn decode_block(block: &Block16, out: &mut [Rgba8]) {
+        out[..16].copy_from_slice(&decode(*block));
+    }
+
+    fn try_decode_block(block: &Block16, out: &mut [Rgba8]) -> Result<(), Error> {
+        out[..16].copy_from_slice(&try_decode(*block)?);
+        Ok(())
+    }
+}
+
+impl Encoder for Bc7 {
+    fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block16 {
+        let pixels: [Rgba8; 16] = input[0..16].try_into().unwrap();
+        encode_with_settings(pixels, settings)
     }
 }
 
 const WEIGHTS_2: [u16; 4] = [0, 21, 43, 64];
-const WEIGHTS_3: [u16; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
-const WEIGHTS_4: [u16; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+pub(crate) const WEIGHTS_3: [u16; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+pub(crate) const WEIGHTS_4: [u16; 16] =
+    [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
 
-const PARTITION_SUBSET_2: [[u8; 16]; 64] = [
+pub(crate) const PARTITION_SUBSET_2: [[u8; 16]; 64] = [
     [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1],
     [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1],
     [0, 1, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1],
@@ -405,27 +1332,16 @@ const PARTITION_SUBSET_3: [[u8; 16]; 64] = [
     [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
 ];
 
-const ANCHOR_INDICES_SUBSET_0: [u8; 64] = [
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-    0, 0, 0, 0, 0, 0, 0, 0, //
-];
-
 #[rustfmt::skip]
-const ANCHOR_INDICES_SUBSET_2_2: [u8; 64] = [
+pub(crate) const ANCHOR_INDICES_SUBSET_2_2: [u8; 64] = [
     15, 15, 15, 15, 15, 15, 15, 15,
     15, 15, 15, 15, 15, 15, 15, 15,
-    15, 02, 08, 02, 02, 08, 08, 15,
-    02, 08, 02, 02, 08, 08, 02, 02,
-    15, 15, 06, 08, 02, 08, 15, 15,
-    02, 08, 02, 02, 02, 15, 15, 06,
-    06, 02, 06, 08, 15, 15, 02, 02,
-    15, 15, 15, 15, 15, 02, 02, 15,
+    15,  2,  8,  2,  2,  8,  8, 15,
+     2,  8,  2,  2,  8,  8,  2,  2,
+    15, 15,  6,  8,  2,  8, 15, 15,
+     2,  8,  2,  2,  2, 15, 15,  6,
+     6,  2,  6,  8, 15, 15,  2,  2,
+    15, 15, 15, 15, 15,  2,  2, 15,
 ];
 
 const ANCHOR_INDICES_SUBSET_3_2: [u8; 64] = [
@@ -449,3 +1365,191 @@ const ANCHOR_INDICES_SUBSET_3_3: [u8; 64] = [
     15, 3, 15, 15, 15, 15, 15, 15, //
     15, 15, 15, 15, 3, 15, 15, 8, //
 ];
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::BitWriter;
+    use crate::{bc1, fixtures, EncodeSettings, Error, Quality, Rgba8};
+
+    use super::{decode, encode, encode_with_settings, try_decode};
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(decode([0; 16]), [Rgba8::MIN; 16]);
+        assert_eq!(try_decode([0; 16]), Err(Error::UnsupportedMode));
+    }
+
+    #[test]
+    fn decode_mode6() {
+        let mut writer = BitWriter::new();
+        writer.write(1 << 6, 7);
+        // R, G, B, A
+        writer.write(0, 7);
+        writer.write(127, 7);
+        writer.write(0, 7);
+        writer.write(127, 7);
+        writer.write(0, 7);
+        writer.write(127, 7);
+        writer.write(127, 7);
+        writer.write(127, 7);
+        // P-bits
+        writer.write(0, 1);
+        writer.write(1, 1);
+        // Anchor index
+        writer.write(0, 3);
+        for _ in 0..15 {
+            writer.write(15, 4);
+        }
+        let block = writer.finish();
+
+        let output = decode(block);
+
+        assert_eq!(output[0], Rgba8::from_array([0, 0, 0, 254]));
+        for texel in &output[1..] {
+            assert_eq!(*texel, Rgba8::from_array([255; 4]));
+        }
+    }
+
+    #[test]
+    fn decode_mode5_rotation() {
+        let mut writer = BitWriter::new();
+        writer.write(1 << 5, 6);
+        // Rotation: swap R and A
+        writer.write(1, 2);
+        // R, G, B
+        writer.write(127, 7);
+        writer.write(127, 7);
+        writer.write(0, 7);
+        writer.write(0, 7);
+        writer.write(0, 7);
+        writer.write(0, 7);
+        // A
+        writer.write(0, 8);
+        writer.write(0, 8);
+        writer.write(0, 31);
+        writer.write(0, 31);
+        let block = writer.finish();
+
+        let output = decode(block);
+        assert_eq!(output, [Rgba8::from_array([0, 0, 0, 255]); 16]);
+    }
+
+    #[test]
+    fn decode_mode4_index_selection() {
+        let mut writer = BitWriter::new();
+        writer.write(1 << 4, 5);
+        // Rotation
+        writer.write(0, 2);
+        // Index selection: colors use the 3-bit indices
+        writer.write(1, 1);
+        // R, G, B
+        writer.write(0, 5);
+        writer.write(31, 5);
+        writer.write(0, 5);
+        writer.write(31, 5);
+        writer.write(0, 5);
+        writer.write(31, 5);
+        // A
+        writer.write(0, 6);
+        writer.write(63, 6);
+        // 2-bit indices
+        writer.write(1, 1);
+        for _ in 0..15 {
+            writer.write(3, 2);
+        }
+        // 3-bit indices
+        writer.write(0b11, 2);
+        for _ in 0..15 {
+            writer.write(0b111, 3);
+        }
+        let block = writer.finish();
+
+        let output = decode(block);
+
+        assert_eq!(output[0], Rgba8::from_array([108, 108, 108, 84]));
+        for texel in &output[1..] {
+            assert_eq!(*texel, Rgba8::from_array([255; 4]));
+        }
+    }
+
+    #[test]
+    fn decode_fixture() {
+        // BC1 is the lower quality format.
+        let output = fixtures::decode(fixtures::BC7, decode);
+        let reference = fixtures::decode(fixtures::BC1, bc1::decode);
+
+        let mse = fixtures::mean_squared_error(output.zip(reference).map(|(lhs, rhs)| {
+            (
+                [lhs.r as f32, lhs.g as f32, lhs.b as f32],
+                [rhs.r as f32, rhs.g as f32, rhs.b as f32],
+            )
+        }));
+        assert!(mse < 25.0);
+    }
+
+    fn squared_error(lhs: &[Rgba8], rhs: &[Rgba8]) -> u32 {
+        lhs.iter()
+            .zip(rhs)
+            .map(|(lhs, rhs)| {
+                let a = lhs.a as i32 - rhs.a as i32;
+                (lhs.to_rgb8().distance(rhs.to_rgb8()) + a * a) as u32
+            })
+            .sum()
+    }
+
+    #[test]
+    fn encode_solid() {
+        let input = [Rgba8::from_array([12, 200, 77, 255]); 16];
+
+        let output = decode(encode(input));
+        assert!(squared_error(&input, &output) <= 16);
+    }
+
+    #[test]
+    fn encode_gradient_alpha() {
+        let mut input = [Rgba8::MIN; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 16;
+            *texel = Rgba8::from_array([v, 255 - v, v / 2, 255 - v / 3]);
+        }
+
+        let output = decode(encode(input));
+        assert!(squared_error(&input, &output) <= 16 * 8);
+    }
+
+    #[test]
+    fn encode_fixture() {
+        let mut error = 0;
+        let mut num_texels = 0;
+        for block in fixtures::BC7.chunks(16).step_by(997) {
+            let input = decode(block.try_into().unwrap());
+            let output = decode(encode(input));
+
+            error += squared_error(&input, &output) as u64;
+            num_texels += 16;
+        }
+
+        let mse = error as f64 / (num_texels * 4) as f64;
+        assert!(mse < 0.5);
+    }
+
+    #[test]
+    fn encode_quality() {
+        let slow = EncodeSettings {
+            quality: Quality::Slow,
+            ..EncodeSettings::DEFAULT
+        };
+
+        let mut normal_error = 0;
+        let mut slow_error = 0;
+        for block in fixtures::BC7.chunks(16).step_by(4999) {
+            let input = decode(block.try_into().unwrap());
+
+            normal_error += squared_error(&input, &decode(encode(input)));
+            slow_error += squared_error(&input, &decode(encode_with_settings(input, &slow)));
+        }
+
+        // Slow searches a superset of the candidates.
+        assert!(slow_error <= normal_error);
+    }
+}
@@ -1,3 +1,7 @@
+/// Reads values from a block in LSB-first bit order.
+///
+/// This is the order used by the BC6H and BC7 formats, where the first bit
+/// of the block is the least significant bit of the first byte.
 #[derive(Clone, Debug)]
 pub(crate) struct BitReader<const N: usize> {
     state: [u8; N],
@@ -12,18 +16,87 @@ impl<const N: usize> BitReader<N> {
         }
     }
 
-    pub fn read(&mut self, bits: usize) -> u32 {
+    /// Reads the next `bits` bits. The first bit read becomes the least
+    /// significant bit of the returned value.
+    pub fn read(&mut self, bits: u8) -> u32 {
         let mut acc = 0;
 
-        for i in self.cursor..self.cursor + bits {
-            let index = i / 8;
-            let shift = 7 - (i % 8);
-            let bit = (self.state[index] as u32 >> shift) & 1;
-            acc = (acc << 1) | bit;
+        for i in 0..bits as usize {
+            let pos = self.cursor + i;
+            let bit = (self.state[pos / 8] as u32 >> (pos % 8)) & 1;
+            acc |= bit << i;
         }
 
-        self.cursor += bits;
+        self.cursor += bits as usize;
 
         acc
     }
 }
+
+/// Writes values into a block in LSB-first bit order.
+///
+/// This is the inverse of [`BitReader`].
+#[derive(Clone, Debug)]
+pub(crate) struct BitWriter<const N: usize> {
+    state: [u8; N],
+    cursor: usize,
+}
+
+impl<const N: usize> BitWriter<N> {
+    pub fn new() -> Self {
+        Self {
+            state: [0; N],
+            cursor: 0,
+        }
+    }
+
+    /// Writes the lowest `bits` bits of `value`, starting with the least
+    /// significant bit.
+    pub fn write(&mut self, value: u32, bits: u8) {
+        for i in 0..bits as usize {
+            let pos = self.cursor + i;
+            let bit = ((value >> i) & 1) as u8;
+            self.state[pos / 8] |= bit << (pos % 8);
+        }
+
+        self.cursor += bits as usize;
+    }
+
+    pub fn finish(self) -> [u8; N] {
+        debug_assert_eq!(self.cursor, N * 8);
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitReader, BitWriter};
+
+    #[test]
+    fn read_lsb_first() {
+        let mut reader = BitReader::new([0b1010_0110, 0b0000_0001]);
+
+        assert_eq!(reader.read(1), 0);
+        assert_eq!(reader.read(2), 0b11);
+        assert_eq!(reader.read(3), 0b100);
+        assert_eq!(reader.read(3), 0b110);
+        assert_eq!(reader.read(7), 0);
+    }
+
+    #[test]
+    fn write_read() {
+        let mut writer = BitWriter::<2>::new();
+        writer.write(0, 1);
+        writer.write(0b11, 2);
+        writer.write(0b100, 3);
+        writer.write(0b110, 3);
+        writer.write(0, 7);
+
+        let block = writer.finish();
+        assert_eq!(block, [0b1010_0110, 0b0000_0001]);
+
+        let mut reader = BitReader::new(block);
+        assert_eq!(reader.read(3), 0b110);
+        assert_eq!(reader.read(6), 0b110_100);
+    }
+}
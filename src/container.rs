@@ -0,0 +1,358 @@
+//! Types shared by the container formats.
+//!
+//! The [`dds`](crate::dds), [`ktx`](crate::ktx) and [`ktx2`](crate::ktx2)
+//! modules describe textures with a [`TextureInfo`] of their own format enum.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::str;
+
+use crate::Error;
+
+/// A format enum of a container, whose formats all use 4x4 blocks.
+pub trait BlockSize: Copy {
+    /// Returns the size of a 4x4 block in bytes.
+    fn block_size(self) -> usize;
+}
+
+/// The dimensions and format of the texture in a container file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureInfo<F> {
+    pub format: F,
+    /// Width of the top mip level in texels.
+    pub width: u32,
+    /// Height of the top mip level in texels.
+    pub height: u32,
+    pub mip_levels: u32,
+    /// Number of array layers, `1` for textures that are not arrays. For
+    /// cubemaps this is the number of cubes.
+    pub array_size: u32,
+    /// Whether every array layer has six cubemap faces.
+    pub cubemap: bool,
+}
+
+impl<F> TextureInfo<F> {
+    /// Returns the number of faces of every array layer, `6` for cubemaps and
+    /// `1` otherwise.
+    #[inline]
+    pub fn faces(&self) -> u32 {
+        if self.cubemap {
+            6
+        } else {
+            1
+        }
+    }
+
+    /// Returns the dimensions of a mip level in texels.
+    pub fn level_dimensions(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Returns `true` if the texture has a mip level of a face of an array
+    /// layer.
+    pub(crate) fn has_subresource(&self, layer: u32, face: u32, level: u32) -> bool {
+        layer < self.array_size && face < self.faces() && level < self.mip_levels
+    }
+
+    /// Returns the index of the image of a face of an array layer within a
+    /// mip level.
+    pub(crate) fn image_index(&self, layer: u32, face: u32) -> usize {
+        layer as usize * self.faces() as usize + face as usize
+    }
+}
+
+impl<F: BlockSize> TextureInfo<F> {
+    /// Returns the size of a single face of a mip level in bytes.
+    pub(crate) fn image_size(&self, level: u32) -> Option<usize> {
+        let (width, height) = self.level_dimensions(level);
+        (width as usize)
+            .div_ceil(4)
+            .checked_mul((height as usize).div_ceil(4))?
+            .checked_mul(self.format.block_size())
+    }
+
+    /// Returns the size of a mip level with all layers and faces in bytes.
+    pub(crate) fn level_size(&self, level: u32) -> Option<usize> {
+        let images = (self.array_size as usize).checked_mul(self.faces() as usize)?;
+        self.image_size(level)?.checked_mul(images)
+    }
+
+    /// Returns the size of all subresources in bytes.
+    pub(crate) fn data_size(&self) -> Option<usize> {
+        (0..self.mip_levels).try_fold(0usize, |acc, level| {
+            acc.checked_add(self.level_size(level)?)
+        })
+    }
+
+    /// Checks the dimensions of the texture and that the size of its data
+    /// fits in `usize`, so the sizes of its subresources cannot overflow.
+    ///
+    /// Parsers and writers accept the same textures, so every parsed texture
+    /// can be written again.
+    pub(crate) fn check_dimensions(&self) -> Result<(), Error> {
+        let invalid = Error::InvalidDimensions {
+            width: self.width,
+            height: self.height,
+        };
+
+        // The smallest level must still be at least one texel in size.
+        let max_levels = 32 - self.width.max(self.height).leading_zeros();
+        if self.width == 0
+            || self.height == 0
+            || self.mip_levels == 0
+            || self.mip_levels > max_levels
+            || self.array_size == 0
+        {
+            return Err(invalid);
+        }
+
+        self.data_size().ok_or(invalid)?;
+        Ok(())
+    }
+}
+
+/// An iterator over the key/value pairs of a KTX 1.1 or KTX 2.0 file.
+///
+/// Values are returned as stored, including the terminating NUL of string
+/// values.
+#[derive(Clone, Debug)]
+pub struct KeyValues<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> KeyValues<'a> {
+    /// Checks all entries of the key/value data.
+    pub(crate) fn parse(data: &'a [u8], big_endian: bool) -> Result<Self, Error> {
+        let mut rest = data;
+        while let Some((_, next)) = next_key_value(rest, big_endian)? {
+            rest = next;
+        }
+
+        Ok(Self { data, big_endian })
+    }
+}
+
+impl<'a> Iterator for KeyValues<'a> {
+    type Item = KeyValue<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The entries were checked when parsing.
+        let (entry, rest) = next_key_value(self.data, self.big_endian).ok()??;
+        self.data = rest;
+        Some(entry)
+    }
+}
+
+/// A key and its value.
+type KeyValue<'a> = (&'a str, &'a [u8]);
+
+/// Splits the first key/value pair off `data`.
+fn next_key_value(data: &[u8], big_endian: bool) -> Result<Option<(KeyValue<'_>, &[u8])>, Error> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+
+    check_len(data, 4)?;
+    let end = (read_u32_endian(data, 0, big_endian) as usize)
+        .checked_add(4)
+        .ok_or(Error::InvalidHeader)?;
+    let entry = data.get(4..end).ok_or(Error::InvalidHeader)?;
+
+    let nul = entry
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(Error::InvalidHeader)?;
+    let key = str::from_utf8(&entry[..nul]).map_err(|_| Error::InvalidHeader)?;
+    let value = &entry[nul + 1..];
+
+    // Every entry is padded to 4 bytes. `end` is at most the length of
+    // `data`, so this cannot overflow.
+    let rest = data.get(end.next_multiple_of(4)..).unwrap_or(&[]);
+    Ok(Some(((key, value), rest)))
+}
+
+/// Appends key/value data in little endian to `out`.
+#[cfg(feature = "alloc")]
+pub(crate) fn write_key_values(
+    out: &mut Vec<u8>,
+    key_values: &[(&str, &[u8])],
+) -> Result<(), Error> {
+    for (key, value) in key_values {
+        if key.is_empty() || key.contains('\0') {
+            return Err(Error::InvalidHeader);
+        }
+
+        let len = u32::try_from(key.len() + 1 + value.len()).map_err(|_| Error::InvalidHeader)?;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(key.as_bytes());
+        out.push(0);
+        out.extend_from_slice(value);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+
+    Ok(())
+}
+
+/// Appends the compressed data of a subresource to `out`, checking that it
+/// is `expected` bytes long.
+#[cfg(feature = "alloc")]
+pub(crate) fn write_image(out: &mut Vec<u8>, data: &[u8], expected: usize) -> Result<(), Error> {
+    if data.len() != expected {
+        return Err(Error::InvalidLength {
+            expected,
+            actual: data.len(),
+        });
+    }
+
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+/// Checks that `bytes` is at least `len` bytes long.
+pub(crate) fn check_len(bytes: &[u8], len: usize) -> Result<(), Error> {
+    if bytes.len() < len {
+        return Err(Error::InvalidLength {
+            expected: len,
+            actual: bytes.len(),
+        });
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads a `u32` in little endian, or in big endian if `big_endian` is set.
+pub(crate) fn read_u32_endian(bytes: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let value = read_u32(bytes, offset);
+    if big_endian {
+        value.swap_bytes()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "alloc")]
+    use alloc::vec::Vec;
+
+    use crate::dds::DxgiFormat;
+    use crate::Error;
+
+    #[cfg(feature = "alloc")]
+    use super::write_key_values;
+    use super::{KeyValues, TextureInfo};
+
+    fn info(width: u32, height: u32, mip_levels: u32, array_size: u32) -> TextureInfo<DxgiFormat> {
+        TextureInfo {
+            format: DxgiFormat::Bc7Unorm,
+            width,
+            height,
+            mip_levels,
+            array_size,
+            cubemap: true,
+        }
+    }
+
+    #[test]
+    fn texture_sizes() {
+        let info = info(8, 6, 4, 2);
+        assert_eq!(info.level_dimensions(2), (2, 1));
+        assert_eq!(info.level_dimensions(3), (1, 1));
+        assert_eq!(info.image_size(0), Some(4 * 16));
+        assert_eq!(info.image_size(1), Some(16));
+        assert_eq!(info.level_size(0), Some(2 * 6 * 4 * 16));
+        assert_eq!(info.data_size(), Some(2 * 6 * 7 * 16));
+
+        assert_eq!(info.image_index(1, 2), 8);
+        assert!(info.has_subresource(1, 5, 3));
+        assert!(!info.has_subresource(2, 0, 0));
+        assert!(!info.has_subresource(0, 6, 0));
+        assert!(!info.has_subresource(0, 0, 4));
+    }
+
+    #[test]
+    fn check_dimensions() {
+        assert_eq!(info(8, 6, 4, 2).check_dimensions(), Ok(()));
+        assert_eq!(info(1, 1, 1, 1).check_dimensions(), Ok(()));
+
+        // Zero sizes, one level too many and more data than fits in memory.
+        for (width, height, mip_levels, array_size) in [
+            (0, 4, 1, 1),
+            (4, 0, 1, 1),
+            (4, 4, 0, 1),
+            (4, 4, 1, 0),
+            (8, 6, 5, 1),
+            (u32::MAX, u32::MAX, 1, u32::MAX),
+        ] {
+            assert_eq!(
+                info(width, height, mip_levels, array_size).check_dimensions(),
+                Err(Error::InvalidDimensions { width, height })
+            );
+        }
+    }
+
+    #[test]
+    fn parse_key_values() {
+        let data = [
+            &10u32.to_le_bytes()[..],
+            b"key\0value\0\0\0",
+            &5u32.to_le_bytes(),
+            b"k\0abc\0\0\0",
+        ]
+        .concat();
+        let pairs = [("key", &b"value\0"[..]), ("k", &b"abc"[..])];
+        assert!(KeyValues::parse(&data, false).unwrap().eq(pairs));
+
+        // The last entry does not need to be padded.
+        assert!(KeyValues::parse(&data[..data.len() - 3], false)
+            .unwrap()
+            .eq(pairs));
+
+        let mut big_endian = data.clone();
+        big_endian[..4].reverse();
+        big_endian[16..20].reverse();
+        assert!(KeyValues::parse(&big_endian, true).unwrap().eq(pairs));
+
+        assert!(KeyValues::parse(&[], false).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn parse_key_values_errors() {
+        let entry = |len: u32, bytes: &[u8]| [&len.to_le_bytes()[..], bytes].concat();
+
+        for data in [
+            // Truncated length, entry longer than the data, no NUL after the
+            // key and a key that is not UTF-8.
+            [0, 0].to_vec(),
+            entry(8, b"key\0"),
+            entry(u32::MAX, b"key\0"),
+            entry(4, b"keys"),
+            entry(4, b"\xff\xfe\0\0"),
+        ] {
+            assert!(KeyValues::parse(&data, false).is_err(), "{data:?}");
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn write_key_values_roundtrip() {
+        let pairs: [(&str, &[u8]); 3] = [("a", b""), ("KTXwriter", b"bcn\0"), ("key", b"v")];
+
+        let mut out = Vec::new();
+        write_key_values(&mut out, &pairs).unwrap();
+        assert_eq!(out.len(), 8 + 20 + 12);
+        assert!(KeyValues::parse(&out, false).unwrap().eq(pairs));
+
+        for key in ["", "a\0b"] {
+            assert_eq!(
+                write_key_values(&mut out, &[(key, b"")]),
+                Err(Error::InvalidHeader)
+            );
+        }
+    }
+}
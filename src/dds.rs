@@ -0,0 +1,626 @@
+//! Reading and writing DirectDraw Surface (DDS) files.
+//!
+//! Both the legacy header with FourCC codes and the DX10 extended header are
+//! supported. Only block compressed formats can be read and written.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::container::{self, read_u32, BlockSize};
+use crate::Error;
+
+const MAGIC: [u8; 4] = *b"DDS ";
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+// Flags only used by the writer.
+#[cfg(feature = "alloc")]
+const DDSD_CAPS: u32 = 0x1;
+#[cfg(feature = "alloc")]
+const DDSD_HEIGHT: u32 = 0x2;
+#[cfg(feature = "alloc")]
+const DDSD_WIDTH: u32 = 0x4;
+#[cfg(feature = "alloc")]
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+#[cfg(feature = "alloc")]
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+#[cfg(feature = "alloc")]
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+
+/// The pixel format contains a FourCC code.
+const DDPF_FOURCC: u32 = 0x4;
+
+#[cfg(feature = "alloc")]
+const DDSCAPS_COMPLEX: u32 = 0x8;
+#[cfg(feature = "alloc")]
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+#[cfg(feature = "alloc")]
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+/// All six `DDSCAPS2_CUBEMAP_*` face flags.
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// A block compressed `DXGI_FORMAT`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DxgiFormat {
+    Bc1Typeless,
+    Bc1Unorm,
+    Bc1UnormSrgb,
+    Bc2Typeless,
+    Bc2Unorm,
+    Bc2UnormSrgb,
+    Bc3Typeless,
+    Bc3Unorm,
+    Bc3UnormSrgb,
+    Bc4Typeless,
+    Bc4Unorm,
+    Bc4Snorm,
+    Bc5Typeless,
+    Bc5Unorm,
+    Bc5Snorm,
+    Bc6hTypeless,
+    Bc6hUf16,
+    Bc6hSf16,
+    Bc7Typeless,
+    Bc7Unorm,
+    Bc7UnormSrgb,
+}
+
+impl DxgiFormat {
+    /// Returns the format with the given `DXGI_FORMAT` value.
+    pub const fn from_u32(value: u32) -> Option<Self> {
+        Some(match value {
+            70 => Self::Bc1Typeless,
+            71 => Self::Bc1Unorm,
+            72 => Self::Bc1UnormSrgb,
+            73 => Self::Bc2Typeless,
+            74 => Self::Bc2Unorm,
+            75 => Self::Bc2UnormSrgb,
+            76 => Self::Bc3Typeless,
+            77 => Self::Bc3Unorm,
+            78 => Self::Bc3UnormSrgb,
+            79 => Self::Bc4Typeless,
+            80 => Self::Bc4Unorm,
+            81 => Self::Bc4Snorm,
+            82 => Self::Bc5Typeless,
+            83 => Self::Bc5Unorm,
+            84 => Self::Bc5Snorm,
+            94 => Self::Bc6hTypeless,
+            95 => Self::Bc6hUf16,
+            96 => Self::Bc6hSf16,
+            97 => Self::Bc7Typeless,
+            98 => Self::Bc7Unorm,
+            99 => Self::Bc7UnormSrgb,
+            _ => return None,
+        })
+    }
+
+    /// Returns the `DXGI_FORMAT` value of the format.
+    pub const fn to_u32(self) -> u32 {
+        match self {
+            Self::Bc1Typeless => 70,
+            Self::Bc1Unorm => 71,
+            Self::Bc1UnormSrgb => 72,
+            Self::Bc2Typeless => 73,
+            Self::Bc2Unorm => 74,
+            Self::Bc2UnormSrgb => 75,
+            Self::Bc3Typeless => 76,
+            Self::Bc3Unorm => 77,
+            Self::Bc3UnormSrgb => 78,
+            Self::Bc4Typeless => 79,
+            Self::Bc4Unorm => 80,
+            Self::Bc4Snorm => 81,
+            Self::Bc5Typeless => 82,
+            Self::Bc5Unorm => 83,
+            Self::Bc5Snorm => 84,
+            Self::Bc6hTypeless => 94,
+            Self::Bc6hUf16 => 95,
+            Self::Bc6hSf16 => 96,
+            Self::Bc7Typeless => 97,
+            Self::Bc7Unorm => 98,
+            Self::Bc7UnormSrgb => 99,
+        }
+    }
+
+    /// Returns the format of a legacy header with the given FourCC code.
+    pub const fn from_four_cc(four_cc: [u8; 4]) -> Option<Self> {
+        Some(match &four_cc {
+            b"DXT1" => Self::Bc1Unorm,
+            // DXT2 and DXT4 only differ in premultiplied alpha.
+            b"DXT2" | b"DXT3" => Self::Bc2Unorm,
+            b"DXT4" | b"DXT5" => Self::Bc3Unorm,
+            b"ATI1" | b"BC4U" => Self::Bc4Unorm,
+            b"BC4S" => Self::Bc4Snorm,
+            b"ATI2" | b"BC5U" => Self::Bc5Unorm,
+            b"BC5S" => Self::Bc5Snorm,
+            _ => return None,
+        })
+    }
+}
+
+impl BlockSize for DxgiFormat {
+    fn block_size(self) -> usize {
+        match self {
+            Self::Bc1Typeless
+            | Self::Bc1Unorm
+            | Self::Bc1UnormSrgb
+            | Self::Bc4Typeless
+            | Self::Bc4Unorm
+            | Self::Bc4Snorm => 8,
+            _ => 16,
+        }
+    }
+}
+
+/// The dimensions and format of the texture in a DDS file.
+pub type TextureInfo = container::TextureInfo<DxgiFormat>;
+
+/// Returns the size of a surface with all mip levels in bytes.
+///
+/// The sizes must have been checked with
+/// [`check_dimensions`](container::TextureInfo::check_dimensions).
+fn surface_size(info: &TextureInfo) -> usize {
+    (0..info.mip_levels)
+        .map(|level| info.image_size(level).unwrap())
+        .sum()
+}
+
+/// A parsed DDS file.
+///
+/// Surfaces are stored one after another. Every array layer holds one surface
+/// per cubemap face, and every surface holds all of its mip levels.
+#[derive(Clone, Debug)]
+pub struct Dds<'a> {
+    info: TextureInfo,
+    data: &'a [u8],
+}
+
+impl<'a> Dds<'a> {
+    /// Parses the headers of a DDS file and checks that it contains the data
+    /// of all subresources.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        container::check_len(bytes, 4 + HEADER_SIZE)?;
+
+        if bytes[..4] != MAGIC || read_u32(bytes, 4) != HEADER_SIZE as u32 {
+            return Err(Error::InvalidHeader);
+        }
+
+        let height = read_u32(bytes, 12);
+        let width = read_u32(bytes, 16);
+        let mip_levels = read_u32(bytes, 28).max(1);
+        let pixel_format_flags = read_u32(bytes, 80);
+        let four_cc: [u8; 4] = bytes[84..88].try_into().unwrap();
+        let caps2 = read_u32(bytes, 112);
+
+        if caps2 & DDSCAPS2_VOLUME != 0 || pixel_format_flags & DDPF_FOURCC == 0 {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        let (format, array_size, cubemap, offset) = if four_cc == *b"DX10" {
+            let header = 4 + HEADER_SIZE;
+            container::check_len(bytes, header + DX10_HEADER_SIZE)?;
+
+            let format =
+                DxgiFormat::from_u32(read_u32(bytes, header)).ok_or(Error::UnsupportedFormat)?;
+            if read_u32(bytes, header + 4) != D3D10_RESOURCE_DIMENSION_TEXTURE2D {
+                return Err(Error::UnsupportedFormat);
+            }
+
+            let cubemap = read_u32(bytes, header + 8) & D3D10_RESOURCE_MISC_TEXTURECUBE != 0;
+            let array_size = read_u32(bytes, header + 12);
+            if array_size == 0 {
+                return Err(Error::InvalidHeader);
+            }
+
+            (format, array_size, cubemap, header + DX10_HEADER_SIZE)
+        } else {
+            let format = DxgiFormat::from_four_cc(four_cc).ok_or(Error::UnsupportedFormat)?;
+
+            // Cubemaps with missing faces cannot be represented by the DX10
+            // header either.
+            let cubemap = caps2 & DDSCAPS2_CUBEMAP != 0;
+            if cubemap && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+                return Err(Error::UnsupportedFormat);
+            }
+
+            (format, 1, cubemap, 4 + HEADER_SIZE)
+        };
+
+        let info = TextureInfo {
+            format,
+            width,
+            height,
+            mip_levels,
+            array_size,
+            cubemap,
+        };
+        info.check_dimensions()?;
+
+        // The data size was checked above and `offset` is small.
+        let end = info.data_size().unwrap() + offset;
+        container::check_len(bytes, end)?;
+
+        Ok(Self {
+            info,
+            data: &bytes[offset..end],
+        })
+    }
+
+    /// Returns the dimensions and format of the texture.
+    #[inline]
+    pub fn info(&self) -> TextureInfo {
+        self.info
+    }
+
+    /// Returns the compressed data of a mip level of a face of an array layer.
+    ///
+    /// Returns `None` if any index is out of range.
+    pub fn subresource(&self, layer: u32, face: u32, level: u32) -> Option<&'a [u8]> {
+        let info = &self.info;
+        if !info.has_subresource(layer, face, level) {
+            return None;
+        }
+
+        // The sizes were checked for overflow when parsing.
+        let mut offset = info.image_index(layer, face) * surface_size(info);
+        for level in 0..level {
+            offset += info.image_size(level).unwrap();
+        }
+
+        let len = info.image_size(level).unwrap();
+        Some(&self.data[offset..offset + len])
+    }
+}
+
+/// Writes a DDS file.
+///
+/// `subresource` returns the compressed data of a mip level of a face of an
+/// array layer, called with the same arguments as [`Dds::subresource`]. BC1
+/// to BC3 textures without array layers are written with a legacy header, all
+/// other textures with a DX10 header.
+#[cfg(feature = "alloc")]
+pub fn write<'a>(
+    info: &TextureInfo,
+    mut subresource: impl FnMut(u32, u32, u32) -> &'a [u8],
+) -> Result<Vec<u8>, Error> {
+    info.check_dimensions()?;
+
+    // The sizes were checked for overflow above.
+    let len = info.data_size().unwrap();
+    let linear_size =
+        u32::try_from(info.image_size(0).unwrap()).map_err(|_| Error::InvalidDimensions {
+            width: info.width,
+            height: info.height,
+        })?;
+
+    let legacy = match info.format {
+        DxgiFormat::Bc1Unorm => Some(*b"DXT1"),
+        DxgiFormat::Bc2Unorm => Some(*b"DXT3"),
+        DxgiFormat::Bc3Unorm => Some(*b"DXT5"),
+        _ => None,
+    }
+    .filter(|_| info.array_size == 1);
+
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_LINEARSIZE;
+    let mut caps = DDSCAPS_TEXTURE;
+    let mut caps2 = 0;
+    if info.mip_levels > 1 {
+        flags |= DDSD_MIPMAPCOUNT;
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+    if info.cubemap {
+        caps |= DDSCAPS_COMPLEX;
+        caps2 |= DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES;
+    }
+
+    let header_size = 4
+        + HEADER_SIZE
+        + if legacy.is_some() {
+            0
+        } else {
+            DX10_HEADER_SIZE
+        };
+    let mut out = Vec::with_capacity(header_size + len);
+
+    out.extend_from_slice(&MAGIC);
+    for value in [
+        HEADER_SIZE as u32,
+        flags,
+        info.height,
+        info.width,
+        linear_size,
+        0,
+        info.mip_levels,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    // dwReserved1
+    out.resize(out.len() + 11 * 4, 0);
+
+    // Pixel format
+    out.extend_from_slice(&32u32.to_le_bytes());
+    out.extend_from_slice(&DDPF_FOURCC.to_le_bytes());
+    out.extend_from_slice(&legacy.unwrap_or(*b"DX10"));
+    out.resize(out.len() + 5 * 4, 0);
+
+    for value in [caps, caps2, 0, 0, 0] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    if legacy.is_none() {
+        let misc_flag = if info.cubemap {
+            D3D10_RESOURCE_MISC_TEXTURECUBE
+        } else {
+            0
+        };
+
+        for value in [
+            info.format.to_u32(),
+            D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+            misc_flag,
+            info.array_size,
+            0,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    debug_assert_eq!(out.len(), header_size);
+
+    for layer in 0..info.array_size {
+        for face in 0..info.faces() {
+            for level in 0..info.mip_levels {
+                let data = subresource(layer, face, level);
+                container::write_image(&mut out, data, info.image_size(level).unwrap())?;
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::container::BlockSize;
+    use crate::Error;
+
+    #[cfg(feature = "alloc")]
+    use super::{write, TextureInfo};
+    use super::{Dds, DxgiFormat};
+
+    #[test]
+    fn parse_fixtures() {
+        let fixtures: [(&[u8], DxgiFormat); 7] = [
+            (
+                include_bytes!("../test_suite/bc1.dds"),
+                DxgiFormat::Bc1Unorm,
+            ),
+            (
+                include_bytes!("../test_suite/bc2.dds"),
+                DxgiFormat::Bc2Unorm,
+            ),
+            (
+                include_bytes!("../test_suite/bc3.dds"),
+                DxgiFormat::Bc3Unorm,
+            ),
+            (
+                include_bytes!("../test_suite/bc4.dds"),
+                DxgiFormat::Bc4Unorm,
+            ),
+            (
+                include_bytes!("../test_suite/bc5.dds"),
+                DxgiFormat::Bc5Unorm,
+            ),
+            (
+                include_bytes!("../test_suite/bc6h.dds"),
+                DxgiFormat::Bc6hUf16,
+            ),
+            (
+                include_bytes!("../test_suite/bc7.dds"),
+                DxgiFormat::Bc7Unorm,
+            ),
+        ];
+
+        for (bytes, format) in fixtures {
+            let dds = Dds::parse(bytes).unwrap();
+            let info = dds.info();
+            assert_eq!(info.format, format);
+            assert_eq!((info.width, info.height), (2560, 1440));
+            assert_eq!((info.mip_levels, info.array_size), (1, 1));
+            assert!(!info.cubemap);
+
+            // The data ends with the only subresource.
+            let data = dds.subresource(0, 0, 0).unwrap();
+            assert_eq!(data.len(), 640 * 360 * format.block_size());
+            assert_eq!(data, &bytes[bytes.len() - data.len()..]);
+
+            assert!(dds.subresource(0, 0, 1).is_none());
+            assert!(dds.subresource(1, 0, 0).is_none());
+            assert!(dds.subresource(0, 1, 0).is_none());
+        }
+    }
+
+    /// Builds a DDS file with a DX10 header where every byte of a subresource
+    /// holds its index.
+    fn dx10_file(format: DxgiFormat, width: u32, height: u32, mips: u32, array: u32) -> Vec<u8> {
+        let mut bytes = alloc::vec![0; 148];
+        bytes[..4].copy_from_slice(b"DDS ");
+        bytes[4..8].copy_from_slice(&124u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[28..32].copy_from_slice(&mips.to_le_bytes());
+        bytes[80..84].copy_from_slice(&4u32.to_le_bytes());
+        bytes[84..88].copy_from_slice(b"DX10");
+        bytes[128..132].copy_from_slice(&format.to_u32().to_le_bytes());
+        bytes[132..136].copy_from_slice(&3u32.to_le_bytes());
+        bytes[136..140].copy_from_slice(&4u32.to_le_bytes());
+        bytes[140..144].copy_from_slice(&array.to_le_bytes());
+
+        let mut index = 0;
+        for _ in 0..array * 6 {
+            for level in 0..mips {
+                let blocks =
+                    (width >> level).max(1).div_ceil(4) * (height >> level).max(1).div_ceil(4);
+                bytes.extend((0..blocks as usize * format.block_size()).map(|_| index));
+                index += 1;
+            }
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn parse_cubemap_array() {
+        let bytes = dx10_file(DxgiFormat::Bc7UnormSrgb, 16, 8, 4, 2);
+        let dds = Dds::parse(&bytes).unwrap();
+
+        let info = dds.info();
+        assert_eq!(info.format, DxgiFormat::Bc7UnormSrgb);
+        assert_eq!((info.mip_levels, info.array_size), (4, 2));
+        assert!(info.cubemap);
+
+        let mut index = 0;
+        for layer in 0..2 {
+            for face in 0..6 {
+                for level in 0..4 {
+                    let data = dds.subresource(layer, face, level).unwrap();
+                    let (width, height) = info.level_dimensions(level);
+                    assert_eq!(
+                        data.len(),
+                        width.div_ceil(4) as usize * height.div_ceil(4) as usize * 16
+                    );
+                    assert!(data.iter().all(|b| *b == index));
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let bytes = dx10_file(DxgiFormat::Bc1Unorm, 8, 8, 2, 1);
+        assert!(Dds::parse(&bytes).is_ok());
+
+        assert_eq!(
+            Dds::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::InvalidLength {
+                expected: bytes.len(),
+                actual: bytes.len() - 1
+            }
+        );
+        assert!(matches!(
+            Dds::parse(&bytes[..100]),
+            Err(Error::InvalidLength { .. })
+        ));
+
+        let mut invalid = bytes.clone();
+        invalid[0] = b'X';
+        assert_eq!(Dds::parse(&invalid).unwrap_err(), Error::InvalidHeader);
+
+        // R8G8B8A8_UNORM
+        let mut invalid = bytes.clone();
+        invalid[128..132].copy_from_slice(&28u32.to_le_bytes());
+        assert_eq!(Dds::parse(&invalid).unwrap_err(), Error::UnsupportedFormat);
+
+        let mut invalid = bytes.clone();
+        invalid[84..88].copy_from_slice(b"DXT9");
+        assert_eq!(Dds::parse(&invalid).unwrap_err(), Error::UnsupportedFormat);
+
+        // Dimensions the writer rejects.
+        let mut invalid = bytes.clone();
+        invalid[16..20].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            Dds::parse(&invalid).unwrap_err(),
+            Error::InvalidDimensions {
+                width: 0,
+                height: 8
+            }
+        );
+
+        let mut invalid = bytes;
+        invalid[28..32].copy_from_slice(&5u32.to_le_bytes());
+        assert_eq!(
+            Dds::parse(&invalid).unwrap_err(),
+            Error::InvalidDimensions {
+                width: 8,
+                height: 8
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn write_fixtures() {
+        for bytes in [
+            &include_bytes!("../test_suite/bc1.dds")[..],
+            &include_bytes!("../test_suite/bc3.dds")[..],
+            &include_bytes!("../test_suite/bc7.dds")[..],
+        ] {
+            let dds = Dds::parse(bytes).unwrap();
+            let data = dds.subresource(0, 0, 0).unwrap();
+
+            let output = write(&dds.info(), |_, _, _| data).unwrap();
+            assert_eq!(output.len(), bytes.len());
+            assert_eq!(output[84..88], bytes[84..88]);
+            assert_eq!(output[20..24], (data.len() as u32).to_le_bytes());
+            assert_eq!(output[bytes.len() - data.len()..], *data);
+
+            let parsed = Dds::parse(&output).unwrap();
+            assert_eq!(parsed.info(), dds.info());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn write_cubemap_array() {
+        let bytes = dx10_file(DxgiFormat::Bc6hSf16, 16, 8, 5, 3);
+        let dds = Dds::parse(&bytes).unwrap();
+
+        let output = write(&dds.info(), |layer, face, level| {
+            dds.subresource(layer, face, level).unwrap()
+        })
+        .unwrap();
+        assert_eq!(output[84..88], *b"DX10");
+
+        // Only the DX10 header is written exactly like the reference.
+        let parsed = Dds::parse(&output).unwrap();
+        assert_eq!(parsed.info(), dds.info());
+        assert_eq!(output[128..], bytes[128..]);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn write_header_kind() {
+        let data = [0; 16];
+        for (format, four_cc) in [
+            (DxgiFormat::Bc1Unorm, *b"DXT1"),
+            (DxgiFormat::Bc1UnormSrgb, *b"DX10"),
+            (DxgiFormat::Bc2Unorm, *b"DXT3"),
+            (DxgiFormat::Bc3Unorm, *b"DXT5"),
+            (DxgiFormat::Bc3Typeless, *b"DX10"),
+            (DxgiFormat::Bc4Unorm, *b"DX10"),
+            (DxgiFormat::Bc7Unorm, *b"DX10"),
+        ] {
+            let info = TextureInfo {
+                format,
+                width: 4,
+                height: 4,
+                mip_levels: 1,
+                array_size: 1,
+                cubemap: false,
+            };
+
+            let output = write(&info, |_, _, _| &data[..format.block_size()]).unwrap();
+            assert_eq!(output[84..88], four_cc);
+            assert_eq!(Dds::parse(&output).unwrap().info(), info);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use core::fmt::{self, Display, Formatter};
+
+/// An error returned by the fallible encoding and decoding functions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Error {
+    /// The buffer does not have the length required by the image dimensions,
+    /// or is too short to hold them.
+    InvalidLength { expected: usize, actual: usize },
+    /// The block uses a reserved mode.
+    UnsupportedMode,
+    /// The dimensions do not fit the image or are too large to address.
+    InvalidDimensions { width: u32, height: u32 },
+    /// The data is stored in a format that cannot be encoded or decoded.
+    UnsupportedFormat,
+    /// The header of a container file is malformed.
+    InvalidHeader,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength { expected, actual } => {
+                write!(
+                    f,
+                    "invalid buffer length: expected {expected} bytes, got {actual}"
+                )
+            }
+            Self::UnsupportedMode => f.write_str("block uses a reserved mode"),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::UnsupportedFormat => f.write_str("unsupported format"),
+            Self::InvalidHeader => f.write_str("invalid container header"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
@@ -0,0 +1,102 @@
+//! The block data of the fixtures in `test_suite`.
+//!
+//! All fixtures were compressed from the same 2560x1440 image by
+//! `test_suite/gen.sh`. Decoding two of them gives close, but not identical
+//! texels, as every format loses different details of the image.
+//!
+//! `gen.sh` also writes the texels compressonator decodes from every fixture
+//! to `test_suite/<format>_decoded.bmp`. These are not checked in, so the tests
+//! comparing against them are ignored by default.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+pub(crate) const BC1: &[u8] = include_bytes!("../test_suite/bc1.dds").split_at(128).1;
+pub(crate) const BC2: &[u8] = include_bytes!("../test_suite/bc2.dds").split_at(128).1;
+pub(crate) const BC3: &[u8] = include_bytes!("../test_suite/bc3.dds").split_at(128).1;
+pub(crate) const BC4: &[u8] = include_bytes!("../test_suite/bc4.dds").split_at(128).1;
+pub(crate) const BC5: &[u8] = include_bytes!("../test_suite/bc5.dds").split_at(128).1;
+// Both use the DX10 header.
+pub(crate) const BC6H: &[u8] = include_bytes!("../test_suite/bc6h.dds").split_at(148).1;
+pub(crate) const BC7: &[u8] = include_bytes!("../test_suite/bc7.dds").split_at(148).1;
+
+const WIDTH: usize = 2560;
+const HEIGHT: usize = 1440;
+
+/// Number of texels in every fixture.
+const NUM_TEXELS: usize = WIDTH * HEIGHT;
+
+/// Decodes the blocks of a fixture and returns their texels in block order.
+pub(crate) fn decode<const N: usize, T>(
+    data: &'static [u8],
+    decode: impl Fn([u8; N]) -> [T; 16],
+) -> impl Iterator<Item = T> {
+    data.chunks_exact(N)
+        .flat_map(move |block| decode(block.try_into().unwrap()))
+}
+
+/// Returns the mean squared error over the channels of all texels of two
+/// decoded fixtures.
+pub(crate) fn mean_squared_error<const C: usize>(
+    texels: impl Iterator<Item = ([f32; C], [f32; C])>,
+) -> f64 {
+    let mut error = 0.0;
+    let mut num_texels = 0;
+    for (lhs, rhs) in texels {
+        for (lhs, rhs) in lhs.into_iter().zip(rhs) {
+            let delta = (lhs - rhs) as f64;
+            error += delta * delta;
+        }
+
+        num_texels += 1;
+    }
+
+    assert_eq!(num_texels, NUM_TEXELS);
+    error / (num_texels * C) as f64
+}
+
+/// Reads the texels compressonator decoded from a fixture and returns their
+/// RGBA channels in block order.
+///
+/// `name` is the name of the bitmap `test_suite/gen.sh` wrote, e.g.
+/// `bc1_decoded.bmp`. Texels of bitmaps without alpha channel are opaque.
+pub(crate) fn read_reference(name: &str) -> Vec<[u8; 4]> {
+    let path = format!("{}/test_suite/{name}", env!("CARGO_MANIFEST_DIR"));
+    let file = std::fs::read(&path).unwrap_or_else(|err| panic!("cannot read {path}: {err}"));
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+    assert_eq!(&file[..2], b"BM");
+    let data = read_u32(10) as usize;
+    let width = read_u32(18) as i32;
+    // Rows are stored bottom-up unless the height is negative.
+    let height = read_u32(22) as i32;
+    let bytes_per_texel = u16::from_le_bytes([file[28], file[29]]) as usize / 8;
+    assert_eq!(
+        (width, height.unsigned_abs()),
+        (WIDTH as i32, HEIGHT as u32)
+    );
+    assert!(matches!(bytes_per_texel, 3 | 4));
+    // Rows are padded to 4 bytes.
+    let pitch = (WIDTH * bytes_per_texel + 3) & !3;
+
+    let mut texels = Vec::with_capacity(NUM_TEXELS);
+    for block_y in (0..HEIGHT).step_by(4) {
+        for block_x in (0..WIDTH).step_by(4) {
+            for y in block_y..block_y + 4 {
+                let row = if height > 0 { HEIGHT - 1 - y } else { y };
+                for x in block_x..block_x + 4 {
+                    let offset = data + row * pitch + x * bytes_per_texel;
+                    let [b, g, r] = file[offset..offset + 3].try_into().unwrap();
+                    let a = if bytes_per_texel == 4 {
+                        file[offset + 3]
+                    } else {
+                        255
+                    };
+                    texels.push([r, g, b, a]);
+                }
+            }
+        }
+    }
+
+    texels
+}
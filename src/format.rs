@@ -0,0 +1,379 @@
+use crate::dds::DxgiFormat;
+use crate::ktx::GlInternalFormat;
+use crate::ktx2::VkFormat;
+use crate::Error;
+
+/// A block compression format selected at runtime.
+///
+/// Containers describe their formats with their own enums, which convert
+/// from and into `Format`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Format {
+    /// BC1 without alpha, the transparent color of three color blocks
+    /// decodes as opaque black.
+    Bc1RgbUnorm,
+    Bc1RgbSrgb,
+    /// BC1 with one bit of alpha.
+    Bc1RgbaUnorm,
+    Bc1RgbaSrgb,
+    Bc2Unorm,
+    Bc2Srgb,
+    Bc3Unorm,
+    Bc3Srgb,
+    Bc4Unorm,
+    Bc4Snorm,
+    Bc5Unorm,
+    Bc5Snorm,
+    Bc6hUf16,
+    Bc6hSf16,
+    Bc7Unorm,
+    Bc7Srgb,
+}
+
+impl Format {
+    /// Returns the width of a block in texels.
+    #[inline]
+    pub const fn block_width(self) -> u32 {
+        4
+    }
+
+    /// Returns the height of a block in texels.
+    #[inline]
+    pub const fn block_height(self) -> u32 {
+        4
+    }
+
+    /// Returns the size of a block in bytes.
+    pub const fn block_size(self) -> usize {
+        match self {
+            Self::Bc1RgbUnorm
+            | Self::Bc1RgbSrgb
+            | Self::Bc1RgbaUnorm
+            | Self::Bc1RgbaSrgb
+            | Self::Bc4Unorm
+            | Self::Bc4Snorm => 8,
+            _ => 16,
+        }
+    }
+
+    /// Returns the number of channels stored in the format.
+    pub const fn channels(self) -> u32 {
+        match self {
+            Self::Bc4Unorm | Self::Bc4Snorm => 1,
+            Self::Bc5Unorm | Self::Bc5Snorm => 2,
+            Self::Bc1RgbUnorm | Self::Bc1RgbSrgb | Self::Bc6hUf16 | Self::Bc6hSf16 => 3,
+            _ => 4,
+        }
+    }
+
+    /// Returns `true` if the format stores half floats instead of normalized
+    /// values.
+    pub const fn is_hdr(self) -> bool {
+        matches!(self, Self::Bc6hUf16 | Self::Bc6hSf16)
+    }
+
+    /// Returns `true` if the format stores signed values.
+    pub const fn is_signed(self) -> bool {
+        matches!(self, Self::Bc4Snorm | Self::Bc5Snorm | Self::Bc6hSf16)
+    }
+
+    /// Returns `true` if the color channels are sRGB encoded.
+    pub const fn is_srgb(self) -> bool {
+        matches!(
+            self,
+            Self::Bc1RgbSrgb | Self::Bc1RgbaSrgb | Self::Bc2Srgb | Self::Bc3Srgb | Self::Bc7Srgb
+        )
+    }
+
+    /// Returns the size of an image of the given dimensions in bytes.
+    ///
+    /// Partial blocks at the edges count as whole blocks. Returns `None` if
+    /// the size overflows `usize`.
+    pub const fn surface_size(self, width: u32, height: u32) -> Option<usize> {
+        let blocks_x = width.div_ceil(self.block_width()) as usize;
+        let blocks_y = height.div_ceil(self.block_height()) as usize;
+
+        match blocks_x.checked_mul(blocks_y) {
+            Some(blocks) => blocks.checked_mul(self.block_size()),
+            None => None,
+        }
+    }
+}
+
+impl TryFrom<DxgiFormat> for Format {
+    type Error = Error;
+
+    /// Returns [`Error::UnsupportedFormat`] for typeless formats.
+    fn try_from(format: DxgiFormat) -> Result<Self, Error> {
+        Ok(match format {
+            DxgiFormat::Bc1Unorm => Self::Bc1RgbaUnorm,
+            DxgiFormat::Bc1UnormSrgb => Self::Bc1RgbaSrgb,
+            DxgiFormat::Bc2Unorm => Self::Bc2Unorm,
+            DxgiFormat::Bc2UnormSrgb => Self::Bc2Srgb,
+            DxgiFormat::Bc3Unorm => Self::Bc3Unorm,
+            DxgiFormat::Bc3UnormSrgb => Self::Bc3Srgb,
+            DxgiFormat::Bc4Unorm => Self::Bc4Unorm,
+            DxgiFormat::Bc4Snorm => Self::Bc4Snorm,
+            DxgiFormat::Bc5Unorm => Self::Bc5Unorm,
+            DxgiFormat::Bc5Snorm => Self::Bc5Snorm,
+            DxgiFormat::Bc6hUf16 => Self::Bc6hUf16,
+            DxgiFormat::Bc6hSf16 => Self::Bc6hSf16,
+            DxgiFormat::Bc7Unorm => Self::Bc7Unorm,
+            DxgiFormat::Bc7UnormSrgb => Self::Bc7Srgb,
+            DxgiFormat::Bc1Typeless
+            | DxgiFormat::Bc2Typeless
+            | DxgiFormat::Bc3Typeless
+            | DxgiFormat::Bc4Typeless
+            | DxgiFormat::Bc5Typeless
+            | DxgiFormat::Bc6hTypeless
+            | DxgiFormat::Bc7Typeless => return Err(Error::UnsupportedFormat),
+        })
+    }
+}
+
+impl From<Format> for DxgiFormat {
+    /// DXGI has no BC1 format without alpha, BC1 is always converted to the
+    /// format with alpha.
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Bc1RgbUnorm | Format::Bc1RgbaUnorm => Self::Bc1Unorm,
+            Format::Bc1RgbSrgb | Format::Bc1RgbaSrgb => Self::Bc1UnormSrgb,
+            Format::Bc2Unorm => Self::Bc2Unorm,
+            Format::Bc2Srgb => Self::Bc2UnormSrgb,
+            Format::Bc3Unorm => Self::Bc3Unorm,
+            Format::Bc3Srgb => Self::Bc3UnormSrgb,
+            Format::Bc4Unorm => Self::Bc4Unorm,
+            Format::Bc4Snorm => Self::Bc4Snorm,
+            Format::Bc5Unorm => Self::Bc5Unorm,
+            Format::Bc5Snorm => Self::Bc5Snorm,
+            Format::Bc6hUf16 => Self::Bc6hUf16,
+            Format::Bc6hSf16 => Self::Bc6hSf16,
+            Format::Bc7Unorm => Self::Bc7Unorm,
+            Format::Bc7Srgb => Self::Bc7UnormSrgb,
+        }
+    }
+}
+
+impl From<VkFormat> for Format {
+    fn from(format: VkFormat) -> Self {
+        match format {
+            VkFormat::Bc1RgbUnorm => Self::Bc1RgbUnorm,
+            VkFormat::Bc1RgbSrgb => Self::Bc1RgbSrgb,
+            VkFormat::Bc1RgbaUnorm => Self::Bc1RgbaUnorm,
+            VkFormat::Bc1RgbaSrgb => Self::Bc1RgbaSrgb,
+            VkFormat::Bc2Unorm => Self::Bc2Unorm,
+            VkFormat::Bc2Srgb => Self::Bc2Srgb,
+            VkFormat::Bc3Unorm => Self::Bc3Unorm,
+            VkFormat::Bc3Srgb => Self::Bc3Srgb,
+            VkFormat::Bc4Unorm => Self::Bc4Unorm,
+            VkFormat::Bc4Snorm => Self::Bc4Snorm,
+            VkFormat::Bc5Unorm => Self::Bc5Unorm,
+            VkFormat::Bc5Snorm => Self::Bc5Snorm,
+            VkFormat::Bc6hUfloat => Self::Bc6hUf16,
+            VkFormat::Bc6hSfloat => Self::Bc6hSf16,
+            VkFormat::Bc7Unorm => Self::Bc7Unorm,
+            VkFormat::Bc7Srgb => Self::Bc7Srgb,
+        }
+    }
+}
+
+impl From<Format> for VkFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Bc1RgbUnorm => Self::Bc1RgbUnorm,
+            Format::Bc1RgbSrgb => Self::Bc1RgbSrgb,
+            Format::Bc1RgbaUnorm => Self::Bc1RgbaUnorm,
+            Format::Bc1RgbaSrgb => Self::Bc1RgbaSrgb,
+            Format::Bc2Unorm => Self::Bc2Unorm,
+            Format::Bc2Srgb => Self::Bc2Srgb,
+            Format::Bc3Unorm => Self::Bc3Unorm,
+            Format::Bc3Srgb => Self::Bc3Srgb,
+            Format::Bc4Unorm => Self::Bc4Unorm,
+            Format::Bc4Snorm => Self::Bc4Snorm,
+            Format::Bc5Unorm => Self::Bc5Unorm,
+            Format::Bc5Snorm => Self::Bc5Snorm,
+            Format::Bc6hUf16 => Self::Bc6hUfloat,
+            Format::Bc6hSf16 => Self::Bc6hSfloat,
+            Format::Bc7Unorm => Self::Bc7Unorm,
+            Format::Bc7Srgb => Self::Bc7Srgb,
+        }
+    }
+}
+
+impl From<GlInternalFormat> for Format {
+    fn from(format: GlInternalFormat) -> Self {
+        match format {
+            GlInternalFormat::Bc1RgbUnorm => Self::Bc1RgbUnorm,
+            GlInternalFormat::Bc1RgbSrgb => Self::Bc1RgbSrgb,
+            GlInternalFormat::Bc1RgbaUnorm => Self::Bc1RgbaUnorm,
+            GlInternalFormat::Bc1RgbaSrgb => Self::Bc1RgbaSrgb,
+            GlInternalFormat::Bc2Unorm => Self::Bc2Unorm,
+            GlInternalFormat::Bc2Srgb => Self::Bc2Srgb,
+            GlInternalFormat::Bc3Unorm => Self::Bc3Unorm,
+            GlInternalFormat::Bc3Srgb => Self::Bc3Srgb,
+            GlInternalFormat::Bc4Unorm => Self::Bc4Unorm,
+            GlInternalFormat::Bc4Snorm => Self::Bc4Snorm,
+            GlInternalFormat::Bc5Unorm => Self::Bc5Unorm,
+            GlInternalFormat::Bc5Snorm => Self::Bc5Snorm,
+            GlInternalFormat::Bc6hUfloat => Self::Bc6hUf16,
+            GlInternalFormat::Bc6hSfloat => Self::Bc6hSf16,
+            GlInternalFormat::Bc7Unorm => Self::Bc7Unorm,
+            GlInternalFormat::Bc7Srgb => Self::Bc7Srgb,
+        }
+    }
+}
+
+impl From<Format> for GlInternalFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Bc1RgbUnorm => Self::Bc1RgbUnorm,
+            Format::Bc1RgbSrgb => Self::Bc1RgbSrgb,
+            Format::Bc1RgbaUnorm => Self::Bc1RgbaUnorm,
+            Format::Bc1RgbaSrgb => Self::Bc1RgbaSrgb,
+            Format::Bc2Unorm => Self::Bc2Unorm,
+            Format::Bc2Srgb => Self::Bc2Srgb,
+            Format::Bc3Unorm => Self::Bc3Unorm,
+            Format::Bc3Srgb => Self::Bc3Srgb,
+            Format::Bc4Unorm => Self::Bc4Unorm,
+            Format::Bc4Snorm => Self::Bc4Snorm,
+            Format::Bc5Unorm => Self::Bc5Unorm,
+            Format::Bc5Snorm => Self::Bc5Snorm,
+            Format::Bc6hUf16 => Self::Bc6hUfloat,
+            Format::Bc6hSf16 => Self::Bc6hSfloat,
+            Format::Bc7Unorm => Self::Bc7Unorm,
+            Format::Bc7Srgb => Self::Bc7Srgb,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bc1::Bc1;
+    use crate::bc2::Bc2;
+    use crate::bc3::Bc3;
+    use crate::bc4::{Bc4, Bc4Snorm};
+    use crate::bc5::{Bc5, Bc5Snorm};
+    use crate::bc6h::{Bc6hSf16, Bc6hUf16};
+    use crate::bc7::Bc7;
+    use crate::container::BlockSize;
+    use crate::dds::DxgiFormat;
+    use crate::ktx::GlInternalFormat;
+    use crate::ktx2::VkFormat;
+    use crate::{BlockFormat, Error};
+
+    use super::Format;
+
+    const FORMATS: [Format; 16] = [
+        Format::Bc1RgbUnorm,
+        Format::Bc1RgbSrgb,
+        Format::Bc1RgbaUnorm,
+        Format::Bc1RgbaSrgb,
+        Format::Bc2Unorm,
+        Format::Bc2Srgb,
+        Format::Bc3Unorm,
+        Format::Bc3Srgb,
+        Format::Bc4Unorm,
+        Format::Bc4Snorm,
+        Format::Bc5Unorm,
+        Format::Bc5Snorm,
+        Format::Bc6hUf16,
+        Format::Bc6hSf16,
+        Format::Bc7Unorm,
+        Format::Bc7Srgb,
+    ];
+
+    fn block<T: BlockFormat>() -> (u32, u32, usize) {
+        (T::BLOCK_WIDTH as u32, T::BLOCK_HEIGHT as u32, T::BLOCK_SIZE)
+    }
+
+    #[test]
+    fn format_blocks() {
+        let blocks = [
+            (Format::Bc1RgbSrgb, block::<Bc1>()),
+            (Format::Bc1RgbaUnorm, block::<Bc1>()),
+            (Format::Bc2Unorm, block::<Bc2>()),
+            (Format::Bc3Srgb, block::<Bc3>()),
+            (Format::Bc4Unorm, block::<Bc4>()),
+            (Format::Bc4Snorm, block::<Bc4Snorm>()),
+            (Format::Bc5Unorm, block::<Bc5>()),
+            (Format::Bc5Snorm, block::<Bc5Snorm>()),
+            (Format::Bc6hUf16, block::<Bc6hUf16>()),
+            (Format::Bc6hSf16, block::<Bc6hSf16>()),
+            (Format::Bc7Unorm, block::<Bc7>()),
+        ];
+
+        for (format, block) in blocks {
+            assert_eq!(
+                (
+                    format.block_width(),
+                    format.block_height(),
+                    format.block_size()
+                ),
+                block,
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn format_flags() {
+        let hdr: usize = FORMATS.iter().filter(|f| f.is_hdr()).count();
+        let signed: usize = FORMATS.iter().filter(|f| f.is_signed()).count();
+        let srgb: usize = FORMATS.iter().filter(|f| f.is_srgb()).count();
+        assert_eq!((hdr, signed, srgb), (2, 3, 5));
+
+        assert!(Format::Bc6hSf16.is_hdr() && Format::Bc6hSf16.is_signed());
+        assert!(!Format::Bc6hUf16.is_signed());
+        assert!(Format::Bc7Srgb.is_srgb() && !Format::Bc7Unorm.is_srgb());
+        assert_eq!(Format::Bc1RgbUnorm.channels(), 3);
+        assert_eq!(Format::Bc1RgbaSrgb.channels(), 4);
+        assert_eq!(Format::Bc4Snorm.channels(), 1);
+        assert_eq!(Format::Bc5Unorm.channels(), 2);
+        assert_eq!(Format::Bc6hUf16.channels(), 3);
+    }
+
+    #[test]
+    fn format_surface_size() {
+        assert_eq!(Format::Bc1RgbaUnorm.surface_size(4, 4), Some(8));
+        assert_eq!(Format::Bc1RgbaUnorm.surface_size(5, 3), Some(16));
+        assert_eq!(Format::Bc7Unorm.surface_size(640, 360), Some(640 * 360));
+        assert_eq!(Format::Bc4Unorm.surface_size(1, 1), Some(8));
+        assert_eq!(Format::Bc3Unorm.surface_size(0, 16), Some(0));
+    }
+
+    #[test]
+    fn format_conversions() {
+        for format in FORMATS {
+            assert_eq!(Format::from(VkFormat::from(format)), format);
+            assert_eq!(Format::from(GlInternalFormat::from(format)), format);
+
+            assert_eq!(DxgiFormat::from(format).block_size(), format.block_size());
+            assert_eq!(VkFormat::from(format).block_size(), format.block_size());
+            assert_eq!(
+                GlInternalFormat::from(format).block_size(),
+                format.block_size()
+            );
+        }
+
+        assert_eq!(
+            Format::try_from(DxgiFormat::Bc7Typeless),
+            Err(Error::UnsupportedFormat)
+        );
+        assert_eq!(VkFormat::from(Format::Bc1RgbUnorm), VkFormat::Bc1RgbUnorm);
+        assert_eq!(
+            GlInternalFormat::from(Format::Bc1RgbSrgb),
+            GlInternalFormat::Bc1RgbSrgb
+        );
+
+        // DXGI only has BC1 with alpha.
+        for format in FORMATS {
+            let alpha = match format {
+                Format::Bc1RgbUnorm => Format::Bc1RgbaUnorm,
+                Format::Bc1RgbSrgb => Format::Bc1RgbaSrgb,
+                _ => format,
+            };
+            assert_eq!(Format::try_from(DxgiFormat::from(format)), Ok(alpha));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Conversions between IEEE 754 half-precision floats and `f32`.
+
+/// Converts the bits of a half-precision float to an `f32`.
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal halfs are normal floats.
+        (0, _) => {
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x3ff;
+            let exponent = 127 - 15 + 1 - shift;
+            sign | (exponent << 23) | (mantissa << 13)
+        }
+        // Infinity and NaN
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
+/// Converts an `f32` to the bits of the nearest half-precision float.
+///
+/// Values that are too large for a half are rounded to infinity.
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Normal floats that are subnormal halfs.
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let shift = (14 - exponent) as u32;
+        let mantissa = mantissa | 0x80_0000;
+        return sign | round_shift(mantissa, shift) as u16;
+    }
+
+    // Rounding may carry into the exponent, which correctly rounds up to
+    // the next power of two or infinity.
+    let value = ((exponent as u32) << 23) | mantissa;
+    sign | round_shift(value, 13) as u16
+}
+
+/// Shifts `value` right by `shift` bits, rounding half to even.
+fn round_shift(value: u32, shift: u32) -> u32 {
+    let truncated = value >> shift;
+    let remainder = value & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+
+    if remainder > half || (remainder == half && truncated & 1 == 1) {
+        truncated + 1
+    } else {
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{f16_to_f32, f32_to_f16};
+
+    #[test]
+    fn f16_to_f32_values() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.33325195);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 5.9604645e-8);
+        assert_eq!(f16_to_f32(0x03ff), 6.097555e-5);
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn f32_to_f16_values() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.33325195), 0x3555);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(5.9604645e-8), 0x0001);
+        assert_eq!(f32_to_f16(6.097555e-5), 0x03ff);
+        assert_eq!(f32_to_f16(1e-9), 0x0000);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN), 0x7e00);
+
+        // Round half to even.
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 / 2048.0), 0x3c02);
+
+        for bits in 0..0x7c00 {
+            assert_eq!(f32_to_f16(f16_to_f32(bits)), bits);
+            assert_eq!(f32_to_f16(f16_to_f32(bits | 0x8000)), bits | 0x8000);
+        }
+    }
+}
@@ -0,0 +1,570 @@
+//! Reading and writing KTX 1.1 files.
+//!
+//! Only block compressed formats are supported. Files in either byte order
+//! are read, files are always written in little endian.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::container::{self, read_u32, read_u32_endian, BlockSize};
+use crate::Error;
+
+pub use crate::container::KeyValues;
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'1', b'1', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+
+const ENDIANNESS: u32 = 0x04030201;
+
+/// Size of the identifier and header.
+const HEADER_SIZE: usize = 64;
+
+// Values of `glBaseInternalFormat`.
+#[cfg(feature = "alloc")]
+const GL_RED: u32 = 0x1903;
+#[cfg(feature = "alloc")]
+const GL_RGB: u32 = 0x1907;
+#[cfg(feature = "alloc")]
+const GL_RGBA: u32 = 0x1908;
+#[cfg(feature = "alloc")]
+const GL_RG: u32 = 0x8227;
+
+/// A block compressed `glInternalFormat`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GlInternalFormat {
+    /// `GL_COMPRESSED_RGB_S3TC_DXT1_EXT`
+    Bc1RgbUnorm,
+    /// `GL_COMPRESSED_SRGB_S3TC_DXT1_EXT`
+    Bc1RgbSrgb,
+    /// `GL_COMPRESSED_RGBA_S3TC_DXT1_EXT`
+    Bc1RgbaUnorm,
+    /// `GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT`
+    Bc1RgbaSrgb,
+    /// `GL_COMPRESSED_RGBA_S3TC_DXT3_EXT`
+    Bc2Unorm,
+    /// `GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT`
+    Bc2Srgb,
+    /// `GL_COMPRESSED_RGBA_S3TC_DXT5_EXT`
+    Bc3Unorm,
+    /// `GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT`
+    Bc3Srgb,
+    /// `GL_COMPRESSED_RED_RGTC1`
+    Bc4Unorm,
+    /// `GL_COMPRESSED_SIGNED_RED_RGTC1`
+    Bc4Snorm,
+    /// `GL_COMPRESSED_RG_RGTC2`
+    Bc5Unorm,
+    /// `GL_COMPRESSED_SIGNED_RG_RGTC2`
+    Bc5Snorm,
+    /// `GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT`
+    Bc6hUfloat,
+    /// `GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT`
+    Bc6hSfloat,
+    /// `GL_COMPRESSED_RGBA_BPTC_UNORM`
+    Bc7Unorm,
+    /// `GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM`
+    Bc7Srgb,
+}
+
+impl GlInternalFormat {
+    /// Returns the format with the given `glInternalFormat` value.
+    pub const fn from_u32(value: u32) -> Option<Self> {
+        Some(match value {
+            0x83f0 => Self::Bc1RgbUnorm,
+            0x83f1 => Self::Bc1RgbaUnorm,
+            0x83f2 => Self::Bc2Unorm,
+            0x83f3 => Self::Bc3Unorm,
+            0x8c4c => Self::Bc1RgbSrgb,
+            0x8c4d => Self::Bc1RgbaSrgb,
+            0x8c4e => Self::Bc2Srgb,
+            0x8c4f => Self::Bc3Srgb,
+            0x8dbb => Self::Bc4Unorm,
+            0x8dbc => Self::Bc4Snorm,
+            0x8dbd => Self::Bc5Unorm,
+            0x8dbe => Self::Bc5Snorm,
+            0x8e8c => Self::Bc7Unorm,
+            0x8e8d => Self::Bc7Srgb,
+            0x8e8e => Self::Bc6hSfloat,
+            0x8e8f => Self::Bc6hUfloat,
+            _ => return None,
+        })
+    }
+
+    /// Returns the `glInternalFormat` value of the format.
+    pub const fn to_u32(self) -> u32 {
+        match self {
+            Self::Bc1RgbUnorm => 0x83f0,
+            Self::Bc1RgbaUnorm => 0x83f1,
+            Self::Bc2Unorm => 0x83f2,
+            Self::Bc3Unorm => 0x83f3,
+            Self::Bc1RgbSrgb => 0x8c4c,
+            Self::Bc1RgbaSrgb => 0x8c4d,
+            Self::Bc2Srgb => 0x8c4e,
+            Self::Bc3Srgb => 0x8c4f,
+            Self::Bc4Unorm => 0x8dbb,
+            Self::Bc4Snorm => 0x8dbc,
+            Self::Bc5Unorm => 0x8dbd,
+            Self::Bc5Snorm => 0x8dbe,
+            Self::Bc7Unorm => 0x8e8c,
+            Self::Bc7Srgb => 0x8e8d,
+            Self::Bc6hSfloat => 0x8e8e,
+            Self::Bc6hUfloat => 0x8e8f,
+        }
+    }
+
+    /// Returns the `glBaseInternalFormat` value of the format.
+    #[cfg(feature = "alloc")]
+    const fn base_internal_format(self) -> u32 {
+        match self {
+            Self::Bc1RgbUnorm | Self::Bc1RgbSrgb | Self::Bc6hUfloat | Self::Bc6hSfloat => GL_RGB,
+            Self::Bc4Unorm | Self::Bc4Snorm => GL_RED,
+            Self::Bc5Unorm | Self::Bc5Snorm => GL_RG,
+            _ => GL_RGBA,
+        }
+    }
+}
+
+impl BlockSize for GlInternalFormat {
+    fn block_size(self) -> usize {
+        match self {
+            Self::Bc1RgbUnorm
+            | Self::Bc1RgbSrgb
+            | Self::Bc1RgbaUnorm
+            | Self::Bc1RgbaSrgb
+            | Self::Bc4Unorm
+            | Self::Bc4Snorm => 8,
+            _ => 16,
+        }
+    }
+}
+
+/// The dimensions and format of the texture in a KTX 1.1 file.
+pub type TextureInfo = container::TextureInfo<GlInternalFormat>;
+
+/// Returns the `imageSize` of a mip level.
+///
+/// This is the size of a single face for cubemaps that are not arrays and
+/// the size of all layers and faces otherwise.
+fn image_size_field(info: &TextureInfo, level: u32) -> Option<usize> {
+    if info.cubemap && info.array_size == 1 {
+        info.image_size(level)
+    } else {
+        info.level_size(level)
+    }
+}
+
+/// Returns the size of a mip level including its `imageSize`.
+fn stored_level_size(info: &TextureInfo, level: u32) -> Option<usize> {
+    // Faces are padded to 4 bytes, which block compressed data always
+    // is already.
+    info.level_size(level)?.checked_add(4)
+}
+
+/// A parsed KTX 1.1 file.
+#[derive(Clone, Debug)]
+pub struct Ktx<'a> {
+    info: TextureInfo,
+    key_values: KeyValues<'a>,
+    data: &'a [u8],
+}
+
+impl<'a> Ktx<'a> {
+    /// Parses the header of a KTX 1.1 file and checks that it contains the
+    /// data of all levels.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        container::check_len(bytes, HEADER_SIZE)?;
+        if bytes[..12] != IDENTIFIER {
+            return Err(Error::InvalidHeader);
+        }
+
+        let big_endian = match read_u32(bytes, 12) {
+            ENDIANNESS => false,
+            value if value == ENDIANNESS.swap_bytes() => true,
+            _ => return Err(Error::InvalidHeader),
+        };
+        let read = |offset| read_u32_endian(bytes, offset, big_endian);
+
+        let gl_type = read(16);
+        let gl_format = read(24);
+        let format = GlInternalFormat::from_u32(read(28)).ok_or(Error::UnsupportedFormat)?;
+        let width = read(36);
+        let height = read(40);
+        let depth = read(44);
+        let array_size = read(48);
+        let faces = read(52);
+        // Zero levels request mip generation, but only the top level is stored.
+        let mip_levels = read(56).max(1);
+        let key_values_len = read(60) as usize;
+
+        // Compressed formats have neither a type nor a format.
+        if gl_type != 0 || gl_format != 0 || depth != 0 {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        if faces != 1 && faces != 6 {
+            return Err(Error::InvalidHeader);
+        }
+
+        let info = TextureInfo {
+            format,
+            width,
+            height,
+            mip_levels,
+            array_size: array_size.max(1),
+            cubemap: faces == 6,
+        };
+        info.check_dimensions()?;
+
+        let data_offset = HEADER_SIZE
+            .checked_add(key_values_len)
+            .ok_or(Error::InvalidHeader)?;
+        container::check_len(bytes, data_offset)?;
+        let key_values = KeyValues::parse(&bytes[HEADER_SIZE..data_offset], big_endian)?;
+
+        let mut offset = data_offset;
+        for level in 0..mip_levels {
+            // The sizes were checked for overflow above.
+            let expected = image_size_field(&info, level).unwrap();
+            container::check_len(bytes, offset + 4)?;
+            if read(offset) as usize != expected {
+                return Err(Error::InvalidHeader);
+            }
+
+            offset = stored_level_size(&info, level)
+                .and_then(|len| len.checked_add(offset))
+                .ok_or(Error::InvalidHeader)?;
+            container::check_len(bytes, offset)?;
+        }
+
+        Ok(Self {
+            info,
+            key_values,
+            data: &bytes[data_offset..offset],
+        })
+    }
+
+    /// Returns the dimensions and format of the texture.
+    #[inline]
+    pub fn info(&self) -> TextureInfo {
+        self.info
+    }
+
+    /// Returns the compressed data of a mip level of a face of an array layer.
+    ///
+    /// Returns `None` if any index is out of range.
+    pub fn subresource(&self, layer: u32, face: u32, level: u32) -> Option<&'a [u8]> {
+        if !self.info.has_subresource(layer, face, level) {
+            return None;
+        }
+
+        // The sizes were checked when parsing.
+        let mut offset = 0;
+        for level in 0..level {
+            offset += stored_level_size(&self.info, level)?;
+        }
+
+        let len = self.info.image_size(level)?;
+        let offset = offset + 4 + self.info.image_index(layer, face) * len;
+        Some(&self.data[offset..offset + len])
+    }
+
+    /// Returns an iterator over the key/value pairs.
+    pub fn key_values(&self) -> KeyValues<'a> {
+        self.key_values.clone()
+    }
+}
+
+/// Writes a KTX 1.1 file.
+///
+/// `subresource` returns the compressed data of a mip level of a face of an
+/// array layer, called with the same arguments as [`Ktx::subresource`].
+#[cfg(feature = "alloc")]
+pub fn write<'a>(
+    info: &TextureInfo,
+    key_values: &[(&str, &[u8])],
+    mut subresource: impl FnMut(u32, u32, u32) -> &'a [u8],
+) -> Result<Vec<u8>, Error> {
+    info.check_dimensions()?;
+    let mut image_sizes = Vec::with_capacity(info.mip_levels as usize);
+    for level in 0..info.mip_levels {
+        // The sizes were checked for overflow above.
+        let size = u32::try_from(image_size_field(info, level).unwrap()).map_err(|_| {
+            Error::InvalidDimensions {
+                width: info.width,
+                height: info.height,
+            }
+        })?;
+        image_sizes.push(size);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&IDENTIFIER);
+    // Arrays with a single layer are written as plain textures.
+    let array_size = if info.array_size == 1 {
+        0
+    } else {
+        info.array_size
+    };
+    for value in [
+        ENDIANNESS,
+        // glType, glTypeSize and glFormat.
+        0,
+        1,
+        0,
+        info.format.to_u32(),
+        info.format.base_internal_format(),
+        info.width,
+        info.height,
+        0,
+        array_size,
+        info.faces(),
+        info.mip_levels,
+        // bytesOfKeyValueData is filled in below.
+        0,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    container::write_key_values(&mut out, key_values)?;
+    let key_values_len =
+        u32::try_from(out.len() - HEADER_SIZE).map_err(|_| Error::InvalidHeader)?;
+    out[60..64].copy_from_slice(&key_values_len.to_le_bytes());
+
+    for (level, image_size) in (0..info.mip_levels).zip(image_sizes) {
+        out.extend_from_slice(&image_size.to_le_bytes());
+
+        // The sizes were checked above and need no cube or mip padding.
+        let expected = info.image_size(level).unwrap();
+        for layer in 0..info.array_size {
+            for face in 0..info.faces() {
+                container::write_image(&mut out, subresource(layer, face, level), expected)?;
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "alloc")]
+    use alloc::vec;
+    #[cfg(feature = "alloc")]
+    use alloc::vec::Vec;
+
+    #[cfg(feature = "alloc")]
+    use crate::container::read_u32;
+    #[cfg(feature = "alloc")]
+    use crate::Error;
+
+    use super::GlInternalFormat;
+    #[cfg(feature = "alloc")]
+    use super::{write, Ktx, TextureInfo, IDENTIFIER};
+
+    #[test]
+    fn gl_internal_format_values() {
+        let values = (0x83f0..=0x83f3)
+            .chain(0x8c4c..=0x8c4f)
+            .chain(0x8dbb..=0x8dbe)
+            .chain(0x8e8c..=0x8e8f);
+        for value in values {
+            assert_eq!(GlInternalFormat::from_u32(value).unwrap().to_u32(), value);
+        }
+
+        assert_eq!(GlInternalFormat::from_u32(0x83f4), None);
+        assert_eq!(GlInternalFormat::from_u32(0x1908), None);
+        assert_eq!(
+            GlInternalFormat::from_u32(0x83f1),
+            Some(GlInternalFormat::Bc1RgbaUnorm)
+        );
+        assert_eq!(
+            GlInternalFormat::from_u32(0x8e8f),
+            Some(GlInternalFormat::Bc6hUfloat)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    fn write_texture(info: &TextureInfo) -> (Vec<u8>, impl Fn(u32, u32, u32) -> Vec<u8>) {
+        let info = *info;
+        let data = move |layer: u32, face: u32, level: u32| -> Vec<u8> {
+            let len = info.image_size(level).unwrap();
+            vec![(layer * 32 + face * 4 + level) as u8; len]
+        };
+
+        let mut subresources = Vec::new();
+        for layer in 0..info.array_size {
+            for face in 0..info.faces() {
+                for level in 0..info.mip_levels {
+                    subresources.push(data(layer, face, level));
+                }
+            }
+        }
+
+        let key_values: [(&str, &[u8]); 2] =
+            [("KTXorientation", b"S=r,T=d\0"), ("KTXwriter", b"bcn\0")];
+        let bytes = write(&info, &key_values, |layer, face, level| {
+            let index = (layer * info.faces() + face) * info.mip_levels + level;
+            &subresources[index as usize]
+        })
+        .unwrap();
+
+        (bytes, data)
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn write_roundtrip() {
+        let layouts = [(1, false), (3, false), (1, true), (2, true)];
+
+        for (array_size, cubemap) in layouts {
+            let info = TextureInfo {
+                format: GlInternalFormat::Bc3Srgb,
+                width: 8,
+                height: 6,
+                mip_levels: 3,
+                array_size,
+                cubemap,
+            };
+            let (bytes, data) = write_texture(&info);
+
+            let ktx = Ktx::parse(&bytes).unwrap();
+            assert_eq!(ktx.info(), info);
+            for layer in 0..info.array_size {
+                for face in 0..info.faces() {
+                    for level in 0..info.mip_levels {
+                        assert_eq!(
+                            ktx.subresource(layer, face, level).unwrap(),
+                            data(layer, face, level)
+                        );
+                    }
+                }
+            }
+            assert_eq!(ktx.subresource(array_size, 0, 0), None);
+            assert_eq!(ktx.subresource(0, info.faces(), 0), None);
+            assert_eq!(ktx.subresource(0, 0, 3), None);
+
+            assert_eq!(
+                ktx.key_values().collect::<Vec<_>>(),
+                [
+                    ("KTXorientation", &b"S=r,T=d\0"[..]),
+                    ("KTXwriter", &b"bcn\0"[..])
+                ]
+            );
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn write_header() {
+        let info = TextureInfo {
+            format: GlInternalFormat::Bc4Snorm,
+            width: 4,
+            height: 8,
+            mip_levels: 2,
+            array_size: 1,
+            cubemap: true,
+        };
+        let bytes = write(&info, &[], |_, _, level| &[0; 16][..16 >> level]).unwrap();
+
+        assert_eq!(bytes[..12], IDENTIFIER);
+        let header: Vec<u32> = (0..13).map(|i| read_u32(&bytes, 12 + i * 4)).collect();
+        assert_eq!(
+            header,
+            [0x04030201, 0, 1, 0, 0x8dbc, 0x1903, 4, 8, 0, 0, 6, 2, 0]
+        );
+
+        // The imageSize of cubemaps that are not arrays is the size of a face.
+        assert_eq!(read_u32(&bytes, 64), 16);
+        assert_eq!(read_u32(&bytes, 64 + 4 + 6 * 16), 8);
+        assert_eq!(bytes.len(), 64 + 4 + 6 * 16 + 4 + 6 * 8);
+
+        let array = TextureInfo {
+            array_size: 2,
+            ..info
+        };
+        let bytes = write(&array, &[], |_, _, level| &[0; 16][..16 >> level]).unwrap();
+        assert_eq!(read_u32(&bytes, 48), 2);
+        assert_eq!(read_u32(&bytes, 64), 2 * 6 * 16);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parse_big_endian() {
+        let info = TextureInfo {
+            format: GlInternalFormat::Bc1RgbUnorm,
+            width: 8,
+            height: 8,
+            mip_levels: 2,
+            array_size: 1,
+            cubemap: false,
+        };
+        let (mut bytes, data) = write_texture(&info);
+
+        // Swap the header, the key/value lengths and the image sizes.
+        let swap = |bytes: &mut [u8], offset: usize| bytes[offset..offset + 4].reverse();
+        for offset in (12..64).step_by(4) {
+            swap(&mut bytes, offset);
+        }
+        swap(&mut bytes, 64);
+        swap(&mut bytes, 64 + 28);
+        swap(&mut bytes, 64 + 48);
+        swap(&mut bytes, 64 + 48 + 4 + 32);
+
+        let ktx = Ktx::parse(&bytes).unwrap();
+        assert_eq!(ktx.info(), info);
+        assert_eq!(ktx.subresource(0, 0, 1).unwrap(), data(0, 0, 1));
+        assert_eq!(ktx.key_values().count(), 2);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parse_errors() {
+        let info = TextureInfo {
+            format: GlInternalFormat::Bc1RgbUnorm,
+            width: 4,
+            height: 4,
+            mip_levels: 1,
+            array_size: 1,
+            cubemap: false,
+        };
+        let bytes = write(&info, &[("key", b"value")], |_, _, _| &[0; 8]).unwrap();
+        assert!(Ktx::parse(&bytes).is_ok());
+
+        let patched = |offset: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            Ktx::parse(&bytes).map(|_| ()).unwrap_err()
+        };
+
+        // Identifier, endianness, type, formats, depth and faces.
+        assert_eq!(patched(0, 0), Error::InvalidHeader);
+        assert_eq!(patched(12, 0x01020403), Error::InvalidHeader);
+        assert_eq!(patched(16, 0x1401), Error::UnsupportedFormat);
+        assert_eq!(patched(28, 0x8058), Error::UnsupportedFormat);
+        assert_eq!(patched(44, 1), Error::UnsupportedFormat);
+        assert_eq!(patched(52, 2), Error::InvalidHeader);
+        // Dimensions the writer rejects.
+        let zero_width = Error::InvalidDimensions {
+            width: 0,
+            height: 4,
+        };
+        assert_eq!(patched(36, 0), zero_width);
+        // Key/value entry length and image size.
+        assert_eq!(patched(64, 64), Error::InvalidHeader);
+        assert_eq!(patched(64 + 16, 16), Error::InvalidHeader);
+
+        assert_eq!(
+            Ktx::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::InvalidLength {
+                expected: bytes.len(),
+                actual: bytes.len() - 1
+            }
+        );
+        assert!(matches!(
+            Ktx::parse(&bytes[..40]).unwrap_err(),
+            Error::InvalidLength { .. }
+        ));
+    }
+}
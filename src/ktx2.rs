@@ -0,0 +1,627 @@
+//! Reading and writing KTX 2.0 files.
+//!
+//! Only block compressed formats without supercompression are supported.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::container::{self, read_u32, BlockSize};
+use crate::Error;
+
+pub use crate::container::KeyValues;
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+
+/// Size of the identifier, header and index.
+const HEADER_SIZE: usize = 80;
+/// Size of an entry in the level index.
+const LEVEL_INDEX_SIZE: usize = 24;
+
+// Values of the basic Data Format Descriptor.
+#[cfg(feature = "alloc")]
+const KHR_DF_MODEL_BC1A: u8 = 128;
+#[cfg(feature = "alloc")]
+const KHR_DF_PRIMARIES_BT709: u8 = 1;
+#[cfg(feature = "alloc")]
+const KHR_DF_TRANSFER_LINEAR: u8 = 1;
+#[cfg(feature = "alloc")]
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+#[cfg(feature = "alloc")]
+const KHR_DF_CHANNEL_ALPHA: u8 = 15;
+#[cfg(feature = "alloc")]
+const KHR_DF_SAMPLE_DATATYPE_SIGNED: u8 = 0x40;
+#[cfg(feature = "alloc")]
+const KHR_DF_SAMPLE_DATATYPE_FLOAT: u8 = 0x80;
+
+/// A block compressed `VkFormat`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VkFormat {
+    Bc1RgbUnorm,
+    Bc1RgbSrgb,
+    Bc1RgbaUnorm,
+    Bc1RgbaSrgb,
+    Bc2Unorm,
+    Bc2Srgb,
+    Bc3Unorm,
+    Bc3Srgb,
+    Bc4Unorm,
+    Bc4Snorm,
+    Bc5Unorm,
+    Bc5Snorm,
+    Bc6hUfloat,
+    Bc6hSfloat,
+    Bc7Unorm,
+    Bc7Srgb,
+}
+
+impl VkFormat {
+    const ALL: [Self; 16] = [
+        Self::Bc1RgbUnorm,
+        Self::Bc1RgbSrgb,
+        Self::Bc1RgbaUnorm,
+        Self::Bc1RgbaSrgb,
+        Self::Bc2Unorm,
+        Self::Bc2Srgb,
+        Self::Bc3Unorm,
+        Self::Bc3Srgb,
+        Self::Bc4Unorm,
+        Self::Bc4Snorm,
+        Self::Bc5Unorm,
+        Self::Bc5Snorm,
+        Self::Bc6hUfloat,
+        Self::Bc6hSfloat,
+        Self::Bc7Unorm,
+        Self::Bc7Srgb,
+    ];
+
+    /// Returns the format with the given `VkFormat` value.
+    pub const fn from_u32(value: u32) -> Option<Self> {
+        // The block compressed formats are numbered consecutively starting
+        // with `VK_FORMAT_BC1_RGB_UNORM_BLOCK`.
+        match value.checked_sub(131) {
+            Some(index) if index < Self::ALL.len() as u32 => Some(Self::ALL[index as usize]),
+            _ => None,
+        }
+    }
+
+    /// Returns the `VkFormat` value of the format.
+    pub const fn to_u32(self) -> u32 {
+        131 + self as u32
+    }
+
+    /// Returns the basic Data Format Descriptor block of the format.
+    #[cfg(feature = "alloc")]
+    fn data_format_descriptor(self) -> Vec<u8> {
+        use VkFormat::*;
+
+        // The color model, sRGB, signed and float flags.
+        let (model, srgb, signed, float) = match self {
+            Bc1RgbUnorm | Bc1RgbaUnorm => (0, false, false, false),
+            Bc1RgbSrgb | Bc1RgbaSrgb => (0, true, false, false),
+            Bc2Unorm => (1, false, false, false),
+            Bc2Srgb => (1, true, false, false),
+            Bc3Unorm => (2, false, false, false),
+            Bc3Srgb => (2, true, false, false),
+            Bc4Unorm => (3, false, false, false),
+            Bc4Snorm => (3, false, true, false),
+            Bc5Unorm => (4, false, false, false),
+            Bc5Snorm => (4, false, true, false),
+            Bc6hUfloat => (5, false, false, true),
+            Bc6hSfloat => (5, false, true, true),
+            Bc7Unorm => (6, false, false, false),
+            Bc7Srgb => (6, true, false, false),
+        };
+
+        // Channel, bit offset and bit length of every sample.
+        let samples: &[(u8, u16, u8)] = match self {
+            Bc1RgbUnorm | Bc1RgbSrgb | Bc4Unorm | Bc4Snorm => &[(0, 0, 64)],
+            Bc1RgbaUnorm | Bc1RgbaSrgb => &[(KHR_DF_CHANNEL_ALPHA, 0, 64)],
+            Bc2Unorm | Bc2Srgb | Bc3Unorm | Bc3Srgb => {
+                &[(KHR_DF_CHANNEL_ALPHA, 0, 64), (0, 64, 64)]
+            }
+            Bc5Unorm | Bc5Snorm => &[(0, 0, 64), (1, 64, 64)],
+            Bc6hUfloat | Bc6hSfloat | Bc7Unorm | Bc7Srgb => &[(0, 0, 128)],
+        };
+
+        let (lower, upper) = match (float, signed) {
+            (true, true) => ((-1.0f32).to_bits(), 1.0f32.to_bits()),
+            (true, false) => (0.0f32.to_bits(), 1.0f32.to_bits()),
+            (false, true) => (i32::MIN as u32, i32::MAX as u32),
+            (false, false) => (0, u32::MAX),
+        };
+
+        let mut qualifiers = 0;
+        if signed {
+            qualifiers |= KHR_DF_SAMPLE_DATATYPE_SIGNED;
+        }
+        if float {
+            qualifiers |= KHR_DF_SAMPLE_DATATYPE_FLOAT;
+        }
+
+        let transfer = if srgb {
+            KHR_DF_TRANSFER_SRGB
+        } else {
+            KHR_DF_TRANSFER_LINEAR
+        };
+
+        let block_size = 24 + 16 * samples.len() as u32;
+        let mut words = Vec::with_capacity(7 + 4 * samples.len());
+        words.push(4 + block_size);
+        // Vendor and descriptor type are both zero.
+        words.push(0);
+        words.push(2 | (block_size << 16));
+        words.push(u32::from_le_bytes([
+            KHR_DF_MODEL_BC1A + model,
+            KHR_DF_PRIMARIES_BT709,
+            transfer,
+            0,
+        ]));
+        // Blocks are 4x4x1x1 texels, stored as one minus the dimension.
+        words.push(u32::from_le_bytes([3, 3, 0, 0]));
+        words.push(self.block_size() as u32);
+        words.push(0);
+
+        for &(channel, offset, length) in samples {
+            words.push(
+                offset as u32 | ((length - 1) as u32) << 16 | ((channel | qualifiers) as u32) << 24,
+            );
+            words.push(0);
+            words.push(lower);
+            words.push(upper);
+        }
+
+        words.into_iter().flat_map(u32::to_le_bytes).collect()
+    }
+}
+
+impl BlockSize for VkFormat {
+    fn block_size(self) -> usize {
+        match self {
+            Self::Bc1RgbUnorm
+            | Self::Bc1RgbSrgb
+            | Self::Bc1RgbaUnorm
+            | Self::Bc1RgbaSrgb
+            | Self::Bc4Unorm
+            | Self::Bc4Snorm => 8,
+            _ => 16,
+        }
+    }
+}
+
+/// The dimensions and format of the texture in a KTX 2.0 file.
+pub type TextureInfo = container::TextureInfo<VkFormat>;
+
+/// A parsed KTX 2.0 file.
+#[derive(Clone, Debug)]
+pub struct Ktx2<'a> {
+    info: TextureInfo,
+    bytes: &'a [u8],
+    key_values: KeyValues<'a>,
+}
+
+impl<'a> Ktx2<'a> {
+    /// Parses the header and index of a KTX 2.0 file and checks that it
+    /// contains the data of all levels.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        container::check_len(bytes, HEADER_SIZE)?;
+        if bytes[..12] != IDENTIFIER {
+            return Err(Error::InvalidHeader);
+        }
+
+        let format = VkFormat::from_u32(read_u32(bytes, 12)).ok_or(Error::UnsupportedFormat)?;
+        let width = read_u32(bytes, 20);
+        let height = read_u32(bytes, 24);
+        let depth = read_u32(bytes, 28);
+        let layer_count = read_u32(bytes, 32);
+        let face_count = read_u32(bytes, 36);
+        // Zero levels request mip generation, but only the top level is stored.
+        let mip_levels = read_u32(bytes, 40).max(1);
+        let supercompression = read_u32(bytes, 44);
+
+        if depth != 0 || supercompression != 0 {
+            return Err(Error::UnsupportedFormat);
+        }
+
+        if face_count != 1 && face_count != 6 {
+            return Err(Error::InvalidHeader);
+        }
+
+        let info = TextureInfo {
+            format,
+            width,
+            height,
+            mip_levels,
+            array_size: layer_count.max(1),
+            cubemap: face_count == 6,
+        };
+        info.check_dimensions()?;
+
+        let key_values = KeyValues::parse(read_range(bytes, 56)?, false)?;
+
+        // Data Format Descriptor, only its bounds are checked.
+        read_range(bytes, 48)?;
+
+        container::check_len(bytes, HEADER_SIZE + LEVEL_INDEX_SIZE * mip_levels as usize)?;
+        for level in 0..mip_levels {
+            let entry = HEADER_SIZE + LEVEL_INDEX_SIZE * level as usize;
+            let offset = read_u64(bytes, entry)?;
+            let len = read_u64(bytes, entry + 8)?;
+
+            // The sizes were checked for overflow above.
+            if len != info.level_size(level).unwrap() {
+                return Err(Error::InvalidHeader);
+            }
+
+            let end = offset.checked_add(len).ok_or(Error::InvalidHeader)?;
+            container::check_len(bytes, end)?;
+        }
+
+        Ok(Self {
+            info,
+            bytes,
+            key_values,
+        })
+    }
+
+    /// Returns the dimensions and format of the texture.
+    #[inline]
+    pub fn info(&self) -> TextureInfo {
+        self.info
+    }
+
+    /// Returns the data of a mip level with all layers and faces.
+    ///
+    /// Returns `None` if the level is out of range.
+    pub fn level(&self, level: u32) -> Option<&'a [u8]> {
+        if level >= self.info.mip_levels {
+            return None;
+        }
+
+        // The level index was checked when parsing.
+        let entry = HEADER_SIZE + LEVEL_INDEX_SIZE * level as usize;
+        let offset = read_u64(self.bytes, entry).ok()?;
+        let len = read_u64(self.bytes, entry + 8).ok()?;
+        Some(&self.bytes[offset..offset + len])
+    }
+
+    /// Returns the compressed data of a mip level of a face of an array layer.
+    ///
+    /// Returns `None` if any index is out of range.
+    pub fn subresource(&self, layer: u32, face: u32, level: u32) -> Option<&'a [u8]> {
+        if !self.info.has_subresource(layer, face, level) {
+            return None;
+        }
+
+        let data = self.level(level)?;
+        let len = self.info.image_size(level)?;
+        let offset = self.info.image_index(layer, face) * len;
+        Some(&data[offset..offset + len])
+    }
+
+    /// Returns an iterator over the key/value pairs.
+    pub fn key_values(&self) -> KeyValues<'a> {
+        self.key_values.clone()
+    }
+}
+
+/// Reads the 32-bit offset and length at `offset` and returns the range of
+/// `bytes` they describe.
+fn read_range(bytes: &[u8], offset: usize) -> Result<&[u8], Error> {
+    let start = read_u32(bytes, offset) as usize;
+    let len = read_u32(bytes, offset + 4) as usize;
+    let end = start.checked_add(len).ok_or(Error::InvalidHeader)?;
+
+    container::check_len(bytes, end)?;
+    Ok(&bytes[start..end])
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<usize, Error> {
+    let value = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    usize::try_from(value).map_err(|_| Error::InvalidHeader)
+}
+
+/// Writes a KTX 2.0 file.
+///
+/// `subresource` returns the compressed data of a mip level of a face of an
+/// array layer, called with the same arguments as [`Ktx2::subresource`].
+/// The key/value pairs are sorted by key as required by the specification.
+#[cfg(feature = "alloc")]
+pub fn write<'a>(
+    info: &TextureInfo,
+    key_values: &[(&str, &[u8])],
+    mut subresource: impl FnMut(u32, u32, u32) -> &'a [u8],
+) -> Result<Vec<u8>, Error> {
+    info.check_dimensions()?;
+
+    let mut key_values = key_values.to_vec();
+    key_values.sort_by_key(|(key, _)| *key);
+
+    let level_index = HEADER_SIZE;
+    let dfd_offset = level_index + LEVEL_INDEX_SIZE * info.mip_levels as usize;
+    let dfd = info.format.data_format_descriptor();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&IDENTIFIER);
+    // `typeSize` is 1 for block compressed formats, arrays with a single
+    // layer are written as plain textures.
+    let layer_count = if info.array_size == 1 {
+        0
+    } else {
+        info.array_size
+    };
+    for value in [
+        info.format.to_u32(),
+        1,
+        info.width,
+        info.height,
+        0,
+        layer_count,
+        info.faces(),
+        info.mip_levels,
+        0,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // The index is filled in once the sizes are known.
+    out.resize(dfd_offset, 0);
+    out.extend_from_slice(&dfd);
+
+    let kvd_offset = out.len();
+    container::write_key_values(&mut out, &key_values)?;
+    let kvd_len = out.len() - kvd_offset;
+
+    // Levels are stored from the smallest to the largest and aligned to the
+    // block size, which is a multiple of 4.
+    for level in (0..info.mip_levels).rev() {
+        out.resize(out.len().next_multiple_of(info.format.block_size()), 0);
+        let offset = out.len();
+
+        // The sizes were checked above.
+        let expected = info.image_size(level).unwrap();
+        for layer in 0..info.array_size {
+            for face in 0..info.faces() {
+                container::write_image(&mut out, subresource(layer, face, level), expected)?;
+            }
+        }
+
+        let entry = level_index + LEVEL_INDEX_SIZE * level as usize;
+        let len = (out.len() - offset) as u64;
+        out[entry..entry + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+        out[entry + 8..entry + 16].copy_from_slice(&len.to_le_bytes());
+        out[entry + 16..entry + 24].copy_from_slice(&len.to_le_bytes());
+    }
+
+    let (kvd_offset, kvd_len) = if kvd_len == 0 {
+        (0, 0)
+    } else {
+        (kvd_offset, kvd_len)
+    };
+
+    // The supercompression global data stays empty.
+    for (index, value) in [dfd_offset, dfd.len(), kvd_offset, kvd_len]
+        .into_iter()
+        .enumerate()
+    {
+        let value = u32::try_from(value).map_err(|_| Error::InvalidHeader)?;
+        out[48 + index * 4..52 + index * 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "alloc")]
+    use alloc::vec;
+    #[cfg(feature = "alloc")]
+    use alloc::vec::Vec;
+
+    #[cfg(feature = "alloc")]
+    use crate::container::BlockSize;
+    #[cfg(feature = "alloc")]
+    use crate::Error;
+
+    use super::VkFormat;
+    #[cfg(feature = "alloc")]
+    use super::{read_u32, write, Ktx2, TextureInfo, IDENTIFIER};
+
+    #[test]
+    fn vk_format_values() {
+        for value in 131..=146 {
+            assert_eq!(VkFormat::from_u32(value).unwrap().to_u32(), value);
+        }
+
+        assert_eq!(VkFormat::from_u32(130), None);
+        assert_eq!(VkFormat::from_u32(147), None);
+        assert_eq!(VkFormat::from_u32(0), None);
+        assert_eq!(VkFormat::from_u32(133), Some(VkFormat::Bc1RgbaUnorm));
+        assert_eq!(VkFormat::from_u32(144), Some(VkFormat::Bc6hSfloat));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn write_roundtrip() {
+        let info = TextureInfo {
+            format: VkFormat::Bc7Srgb,
+            width: 8,
+            height: 6,
+            mip_levels: 3,
+            array_size: 2,
+            cubemap: true,
+        };
+        let data = |layer: u32, face: u32, level: u32| -> Vec<u8> {
+            let len = [64, 16, 16][level as usize];
+            vec![(layer * 32 + face * 4 + level) as u8; len]
+        };
+        let subresources: Vec<Vec<Vec<Vec<u8>>>> = (0..2)
+            .map(|layer| {
+                (0..6)
+                    .map(|face| (0..3).map(|level| data(layer, face, level)).collect())
+                    .collect()
+            })
+            .collect();
+
+        let key_values: [(&str, &[u8]); 2] = [("KTXwriter", b"bcn\0"), ("KTXorientation", b"rd\0")];
+        let bytes = write(&info, &key_values, |layer, face, level| {
+            &subresources[layer as usize][face as usize][level as usize]
+        })
+        .unwrap();
+
+        let ktx = Ktx2::parse(&bytes).unwrap();
+        assert_eq!(ktx.info(), info);
+        for layer in 0..2 {
+            for face in 0..6 {
+                for level in 0..3 {
+                    assert_eq!(
+                        ktx.subresource(layer, face, level).unwrap(),
+                        data(layer, face, level)
+                    );
+                }
+            }
+        }
+        assert_eq!(ktx.subresource(2, 0, 0), None);
+        assert_eq!(ktx.subresource(0, 6, 0), None);
+        assert_eq!(ktx.subresource(0, 0, 3), None);
+
+        // Keys are sorted and levels are stored smallest first.
+        assert_eq!(
+            ktx.key_values().collect::<Vec<_>>(),
+            [
+                ("KTXorientation", &b"rd\0"[..]),
+                ("KTXwriter", &b"bcn\0"[..])
+            ]
+        );
+        let offset = |level: usize| read_u32(&bytes, 80 + 24 * level);
+        assert!(offset(2) < offset(1) && offset(1) < offset(0));
+        assert!((0..3).all(|level| offset(level) % 16 == 0));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn write_header() {
+        let info = TextureInfo {
+            format: VkFormat::Bc1RgbaSrgb,
+            width: 4,
+            height: 4,
+            mip_levels: 1,
+            array_size: 1,
+            cubemap: false,
+        };
+        let bytes = write(&info, &[], |_, _, _| &[0; 8]).unwrap();
+
+        assert_eq!(bytes[..12], IDENTIFIER);
+        // vkFormat, typeSize, width, height, depth, layers, faces, levels and
+        // supercompression.
+        let header: Vec<u32> = (0..9).map(|i| read_u32(&bytes, 12 + i * 4)).collect();
+        assert_eq!(header, [134, 1, 4, 4, 0, 0, 1, 1, 0]);
+
+        // A single 64-bit alpha sample.
+        let dfd_offset = read_u32(&bytes, 48) as usize;
+        let dfd_len = read_u32(&bytes, 52) as usize;
+        let dfd: Vec<u32> = (0..dfd_len / 4)
+            .map(|i| read_u32(&bytes, dfd_offset + i * 4))
+            .collect();
+        assert_eq!(
+            dfd,
+            [
+                44,
+                0,
+                2 | 40 << 16,
+                128 | 1 << 8 | 2 << 16,
+                3 | 3 << 8,
+                8,
+                0,
+                63 << 16 | 15 << 24,
+                0,
+                0,
+                u32::MAX
+            ]
+        );
+
+        // No key/value data.
+        assert_eq!(read_u32(&bytes, 56), 0);
+        assert_eq!(read_u32(&bytes, 60), 0);
+        // The level is aligned to the block size.
+        assert_eq!(read_u32(&bytes, 80), 152);
+        assert_eq!(bytes.len(), 152 + 8);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn write_data_format_descriptors() {
+        // Number of samples and the first sample's channel and qualifiers.
+        let cases = [
+            (VkFormat::Bc1RgbUnorm, 1, 0x00),
+            (VkFormat::Bc2Unorm, 2, 0x0f),
+            (VkFormat::Bc3Srgb, 2, 0x0f),
+            (VkFormat::Bc4Snorm, 1, 0x40),
+            (VkFormat::Bc5Unorm, 2, 0x00),
+            (VkFormat::Bc6hUfloat, 1, 0x80),
+            (VkFormat::Bc6hSfloat, 1, 0xc0),
+            (VkFormat::Bc7Unorm, 1, 0x00),
+        ];
+
+        for (format, samples, channel) in cases {
+            let dfd = format.data_format_descriptor();
+            assert_eq!(dfd.len(), 28 + 16 * samples, "{format:?}");
+            assert_eq!(read_u32(&dfd, 0) as usize, dfd.len());
+            assert_eq!(read_u32(&dfd, 20) as usize, format.block_size());
+            assert_eq!(dfd[31], channel, "{format:?}");
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parse_errors() {
+        let info = TextureInfo {
+            format: VkFormat::Bc1RgbUnorm,
+            width: 4,
+            height: 4,
+            mip_levels: 1,
+            array_size: 1,
+            cubemap: false,
+        };
+        let bytes = write(&info, &[("key", b"value")], |_, _, _| &[0; 8]).unwrap();
+        assert!(Ktx2::parse(&bytes).is_ok());
+
+        let patched = |offset: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            Ktx2::parse(&bytes).map(|_| ()).unwrap_err()
+        };
+
+        // Identifier, formats, depth, faces and supercompression.
+        assert_eq!(patched(0, 0), Error::InvalidHeader);
+        assert_eq!(patched(12, 37), Error::UnsupportedFormat);
+        assert_eq!(patched(28, 1), Error::UnsupportedFormat);
+        assert_eq!(patched(36, 2), Error::InvalidHeader);
+        assert_eq!(patched(44, 1), Error::UnsupportedFormat);
+        // Dimensions the writer rejects.
+        let zero_width = Error::InvalidDimensions {
+            width: 0,
+            height: 4,
+        };
+        assert_eq!(patched(20, 0), zero_width);
+        // Level length and key/value entry length.
+        assert_eq!(patched(88, 16), Error::InvalidHeader);
+        let kvd_offset = read_u32(&bytes, 56) as usize;
+        assert_eq!(patched(kvd_offset, 64), Error::InvalidHeader);
+        assert_eq!(patched(kvd_offset, u32::MAX), Error::InvalidHeader);
+
+        assert_eq!(
+            Ktx2::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::InvalidLength {
+                expected: bytes.len(),
+                actual: bytes.len() - 1
+            }
+        );
+        assert!(matches!(
+            Ktx2::parse(&bytes[..40]).unwrap_err(),
+            Error::InvalidLength { .. }
+        ));
+    }
+}
@@ -1,22 +1,42 @@
 #![no_std]
 
+#[cfg(any(feature = "alloc", test))]
 extern crate alloc;
 
-//#[cfg(test)]
-//extern crate std;
+#[cfg(test)]
 extern crate std;
 
 pub mod bc1;
 pub mod bc2;
+pub mod bc3;
+pub mod bc4;
+pub mod bc5;
+pub mod bc6h;
 pub mod bc7;
+pub mod container;
+pub mod dds;
+pub mod ktx;
+pub mod ktx2;
+#[cfg(feature = "alloc")]
 pub mod stream;
 
 mod bits;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod format;
+mod half;
+mod settings;
+mod vec3;
+
+pub use error::Error;
+pub use format::Format;
+pub use settings::{EncodeSettings, Metric, Quality};
 
 pub type Block8 = [u8; 8];
 pub type Block16 = [u8; 16];
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Rgba8 {
     pub r: u8,
     pub g: u8,
@@ -67,12 +87,6 @@ impl Rgb8 {
         b: u8::MIN,
     };
 
-    const MAX: Self = Self {
-        r: u8::MAX,
-        g: u8::MAX,
-        b: u8::MAX,
-    };
-
     #[inline]
     pub const fn from_arry(v: [u8; 3]) -> Self {
         Self {
@@ -81,6 +95,16 @@ impl Rgb8 {
             b: v[2],
         }
     }
+}
+
+/// Helpers for comparing colors in tests.
+#[cfg(test)]
+impl Rgb8 {
+    const MAX: Self = Self {
+        r: u8::MAX,
+        g: u8::MAX,
+        b: u8::MAX,
+    };
 
     fn distance(self, other: Self) -> i32 {
         let r = self.r as i32 - other.r as i32;
@@ -91,32 +115,79 @@ impl Rgb8 {
     }
 }
 
-fn read_u16_le(a: u8, b: u8) -> u16 {
-    u16::from_le_bytes([a, b])
+/// A texel with 32-bit floating point channels.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rgb32F {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
 }
 
-pub trait Encoder: private::Sealed {}
-
-pub trait Decoder: private::Sealed {}
-
-mod private {
-    use crate::Rgba8;
-
-    pub trait Sealed {
-        /// Input block size.
-        const BLOCK_SIZE: usize;
+impl Rgb32F {
+    /// Creates a texel from the bits of half-precision floats.
+    #[inline]
+    pub fn from_f16_bits(v: [u16; 3]) -> Self {
+        Self {
+            r: half::f16_to_f32(v[0]),
+            g: half::f16_to_f32(v[1]),
+            b: half::f16_to_f32(v[2]),
+        }
+    }
+}
 
-        /// Number of pixels in both direction for each block.
-        const NUM_PIXELS: usize;
+fn read_u16_le(a: u8, b: u8) -> u16 {
+    u16::from_le_bytes([a, b])
+}
 
-        /// decode(&[u8; Self::BLOCK_SIZE], out: &mut [Rgba8; Self::NUM_PIXELS * Self::NUM_PIXELS]);
-        fn decode(block: &[u8], out: &mut [Rgba8]);
+/// A block compression format.
+///
+/// Images are split into blocks of [`BLOCK_WIDTH`](Self::BLOCK_WIDTH) by
+/// [`BLOCK_HEIGHT`](Self::BLOCK_HEIGHT) texels, each compressed into
+/// [`BLOCK_SIZE`](Self::BLOCK_SIZE) bytes. Implement this trait together with
+/// [`Decoder`] and [`Encoder`] to use a custom format with the streams.
+pub trait BlockFormat {
+    /// The texel type of the format.
+    type Pixel: Copy + Default;
+
+    /// A compressed block, e.g. [`Block8`] or [`Block16`].
+    type Block: Copy + Default + AsRef<[u8]> + AsMut<[u8]>;
+
+    /// Width of a block in texels, which must not be zero.
+    const BLOCK_WIDTH: usize;
+
+    /// Height of a block in texels, which must not be zero.
+    const BLOCK_HEIGHT: usize;
+
+    /// Size of a compressed block in bytes. This must be the length of
+    /// [`Block`](Self::Block), the streams return
+    /// [`Error::UnsupportedFormat`] otherwise.
+    const BLOCK_SIZE: usize;
+}
 
-        /// encode(&[Rgba8; Self::NUM_PIXELS * Self::NUM_PIXELS], block: &mut [u8; Self::BLOCK_SIZE]);
-        fn encode(input: &[Rgba8], block: &mut [u8]);
+/// A [`BlockFormat`] that can be decoded.
+pub trait Decoder: BlockFormat {
+    /// Decodes `block` into the first `BLOCK_WIDTH * BLOCK_HEIGHT` texels of
+    /// `out` in row-major order.
+    fn decode_block(block: &Self::Block, out: &mut [Self::Pixel]);
+
+    /// Decodes `block` like [`decode_block`](Self::decode_block), but returns
+    /// an error if the block cannot be decoded, e.g. because it uses a
+    /// reserved mode. The streams decode blocks with this method.
+    ///
+    /// The default implementation never fails.
+    fn try_decode_block(block: &Self::Block, out: &mut [Self::Pixel]) -> Result<(), Error> {
+        Self::decode_block(block, out);
+        Ok(())
     }
 }
 
+/// A [`BlockFormat`] that can be encoded.
+pub trait Encoder: BlockFormat {
+    /// Encodes the first `BLOCK_WIDTH * BLOCK_HEIGHT` texels of `input` in
+    /// row-major order into a block.
+    fn encode_block(input: &[Self::Pixel], settings: &EncodeSettings) -> Self::Block;
+}
+
 #[cfg(test)]
 mod tests {
     use crate::Rgb8;
@@ -0,0 +1,288 @@
+use crate::bc1::DEFAULT_ALPHA_THRESHOLD;
+
+/// Trade-off between encoding speed and quality.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Quality {
+    /// Endpoints are taken from the principal axis without any refinement.
+    Fast,
+    /// Endpoints are refined with a few least-squares passes.
+    #[default]
+    Normal,
+    /// Searches more candidates, e.g. cluster fit for BC1 to BC3 and more
+    /// partitions for BC6H and BC7.
+    Slow,
+    /// Searches all candidates the encoders support. This is very slow.
+    Exhaustive,
+}
+
+/// How the error between two colors is measured.
+///
+/// All metrics give the same error for a difference of equal magnitude in all
+/// color channels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// All color channels contribute equally.
+    #[default]
+    Uniform,
+    /// Channels are weighted by their contribution to luma, using the
+    /// Rec. 601 coefficients 0.299, 0.587 and 0.114.
+    Luma,
+    /// The error is measured in YCbCr, with luma weighted twice as much as
+    /// chroma. Unlike [`Metric::Luma`] this still penalizes hue shifts that
+    /// keep the luma, e.g. in skin tones.
+    Perceptual,
+}
+
+impl Metric {
+    /// Returns the matrix `M` so that the error of a color difference `d` is
+    /// `d^T * M * d`.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Self::Uniform => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Self::Luma => {
+                let [r, g, b] = LUMA.map(|v| v * 3.0);
+                [[r, 0.0, 0.0], [0.0, g, 0.0], [0.0, 0.0, b]]
+            }
+            Self::Perceptual => {
+                // Rows of the RGB to YCbCr transform and their weights.
+                let y = LUMA;
+                let cb = [-y[0] * CB_SCALE, -y[1] * CB_SCALE, (1.0 - y[2]) * CB_SCALE];
+                let cr = [(1.0 - y[0]) * CR_SCALE, -y[1] * CR_SCALE, -y[2] * CR_SCALE];
+                let rows = [(y, 3.0), (cb, 1.5), (cr, 1.5)];
+
+                let mut matrix = [[0.0; 3]; 3];
+                for (row, weight) in rows {
+                    for i in 0..3 {
+                        for j in 0..3 {
+                            matrix[i][j] += row[i] * row[j] * weight;
+                        }
+                    }
+                }
+
+                matrix
+            }
+        }
+    }
+}
+
+/// Rec. 601 luma coefficients.
+const LUMA: [f32; 3] = [0.299, 0.587, 0.114];
+
+/// Scales `B - Y` and `R - Y` to the range of luma.
+const CB_SCALE: f32 = 0.5 / (1.0 - 0.114);
+const CR_SCALE: f32 = 0.5 / (1.0 - 0.299);
+
+/// The error metric of an encoder, combining the [`Metric`] and the channel
+/// weights of the [`EncodeSettings`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ErrorMetric {
+    /// The error of a color difference `d` is `d^T * color * d`.
+    pub color: [[f32; 3]; 3],
+    /// The weight of the squared alpha difference.
+    pub alpha: f32,
+}
+
+impl ErrorMetric {
+    /// Returns the inner product of two colors under the metric.
+    #[inline]
+    pub fn dot(&self, lhs: [f32; 3], rhs: [f32; 3]) -> f32 {
+        let mut acc = 0.0;
+        for (value, row) in lhs.iter().zip(&self.color) {
+            acc += value * (row[0] * rhs[0] + row[1] * rhs[1] + row[2] * rhs[2]);
+        }
+
+        acc
+    }
+
+    /// Returns the error of a color difference.
+    #[inline]
+    pub fn color_error(&self, delta: [f32; 3]) -> f32 {
+        self.dot(delta, delta)
+    }
+}
+
+/// Settings shared by all block encoders.
+///
+/// Formats ignore the settings that do not apply to them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EncodeSettings {
+    pub quality: Quality,
+    /// Error weights of the red, green, blue and alpha channels. They are
+    /// applied on top of the [`Metric`].
+    pub weights: [f32; 4],
+    /// Texels with a lower alpha value are encoded as transparent in formats
+    /// with 1-bit alpha.
+    pub alpha_threshold: u8,
+    pub metric: Metric,
+    /// Whether quantization error is diffused to neighbouring texels of the
+    /// block. Only used by BC1, BC2 and BC3.
+    pub dither: bool,
+    /// Whether the color error of every texel is scaled by its alpha, so the
+    /// colors of nearly transparent texels matter less. Only used by BC2, BC3
+    /// and BC7.
+    pub weight_color_by_alpha: bool,
+}
+
+impl EncodeSettings {
+    pub const DEFAULT: Self = Self {
+        quality: Quality::Normal,
+        weights: [1.0; 4],
+        alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+        metric: Metric::Uniform,
+        dither: false,
+        weight_color_by_alpha: false,
+    };
+
+    /// Returns the scale of the color error of a texel with alpha `a`.
+    ///
+    /// The scale is never zero, so fully transparent blocks still get a fit.
+    pub(crate) fn alpha_weight(&self, a: u8) -> f32 {
+        if self.weight_color_by_alpha {
+            (a as f32 + 1.0) / 256.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns the error metric, scaling the metric by the channel weights.
+    pub(crate) fn error_metric(&self) -> ErrorMetric {
+        // The weights scale the error of their channel, so every entry is
+        // scaled by the square root of the weights of its row and column.
+        let scale = self.weights.map(|w| sqrt(w.max(0.0)));
+        let mut color = self.metric.matrix();
+        for (i, row) in color.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value *= scale[i] * scale[j];
+            }
+        }
+
+        ErrorMetric {
+            color,
+            alpha: self.weights[3],
+        }
+    }
+}
+
+impl Default for EncodeSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Square root using Newton's method, since `f32::sqrt` requires `std`.
+fn sqrt(value: f32) -> f32 {
+    if value <= 0.0 {
+        return 0.0;
+    }
+
+    // Halving the exponent gives an estimate within a few percent.
+    let mut x = f32::from_bits((value.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..4 {
+        x = 0.5 * (x + value / x);
+    }
+
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bc2::Bc2;
+    use crate::bc3::Bc3;
+    use crate::bc7::Bc7;
+    use crate::{Decoder, Encoder, Rgba8};
+
+    use super::{sqrt, EncodeSettings, Metric};
+
+    #[test]
+    fn sqrt_values() {
+        for value in [0.0f32, 1e-6, 0.25, 1.0, 2.0, 100.0, 1e6] {
+            assert!((sqrt(value) - value.sqrt()).abs() <= value.sqrt() * 1e-6);
+        }
+    }
+
+    #[test]
+    fn metric_gray() {
+        for metric in [Metric::Uniform, Metric::Luma, Metric::Perceptual] {
+            let settings = EncodeSettings {
+                metric,
+                ..EncodeSettings::DEFAULT
+            };
+
+            let error = settings.error_metric().color_error([1.0; 3]);
+            assert!((error - 3.0).abs() < 1e-5, "{metric:?}: {error}");
+        }
+    }
+
+    #[test]
+    fn metric_weights() {
+        let settings = EncodeSettings {
+            weights: [4.0, 1.0, 0.0, 2.0],
+            metric: Metric::Perceptual,
+            ..EncodeSettings::DEFAULT
+        };
+        let weighted = settings.error_metric();
+        let plain = EncodeSettings {
+            metric: Metric::Perceptual,
+            ..EncodeSettings::DEFAULT
+        }
+        .error_metric();
+
+        // A weight of 4 doubles the difference in red.
+        let lhs = weighted.color_error([1.0, 1.0, 5.0]);
+        let rhs = plain.color_error([2.0, 1.0, 0.0]);
+        assert!((lhs - rhs).abs() < 1e-4);
+        assert_eq!(weighted.alpha, 2.0);
+    }
+
+    /// Returns the color error of the opaque texels of a block of opaque
+    /// gray texels with transparent red texels in between, without and with
+    /// weighting the color error by alpha.
+    fn opaque_errors<T>() -> (i32, i32)
+    where
+        T: Encoder<Pixel = Rgba8> + Decoder,
+    {
+        let mut input = [Rgba8::MIN; 16];
+        for (index, texel) in input.iter_mut().enumerate() {
+            let v = index as u8 * 16;
+            *texel = if index % 2 == 0 {
+                Rgba8::from_array([v, v, v, 255])
+            } else {
+                Rgba8::from_array([255, 0, 0, 0])
+            };
+        }
+
+        let opaque_error = |settings: &EncodeSettings| -> i32 {
+            let mut output = [Rgba8::MIN; 16];
+            T::decode_block(&T::encode_block(&input, settings), &mut output);
+
+            input
+                .iter()
+                .zip(output)
+                .filter(|(texel, _)| texel.a == 255)
+                .map(|(lhs, rhs)| lhs.to_rgb8().distance(rhs.to_rgb8()))
+                .sum()
+        };
+
+        let weighted = EncodeSettings {
+            weight_color_by_alpha: true,
+            ..EncodeSettings::DEFAULT
+        };
+        (
+            opaque_error(&EncodeSettings::DEFAULT),
+            opaque_error(&weighted),
+        )
+    }
+
+    #[test]
+    fn weight_color_by_alpha() {
+        let errors = [
+            ("BC2", opaque_errors::<Bc2>()),
+            ("BC3", opaque_errors::<Bc3>()),
+            ("BC7", opaque_errors::<Bc7>()),
+        ];
+
+        for (format, (uniform, weighted)) in errors {
+            assert!(weighted < uniform, "{format}: {weighted} >= {uniform}");
+        }
+    }
+}
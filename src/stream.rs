@@ -1,8 +1,62 @@
+use alloc::vec;
 use alloc::vec::Vec;
-use image::{ImageBuffer, Rgba};
+#[cfg(feature = "image")]
+use image::{DynamicImage, ImageBuffer, Pixel, Rgb, Rgba};
 
+#[cfg(feature = "image")]
 use crate::bc1::Bc1;
-use crate::{Decoder, Encoder, Rgba8};
+use crate::{BlockFormat, Decoder, EncodeSettings, Encoder, Error, Rgb32F, Rgba8};
+
+/// A texel that can be read from and written to a byte buffer.
+pub trait BytePixel: Copy {
+    /// Size of a texel in bytes.
+    const SIZE: usize;
+
+    /// Reads a texel from the first [`SIZE`](Self::SIZE) bytes of `bytes`.
+    fn read_bytes(bytes: &[u8]) -> Self;
+
+    /// Writes the texel to the first [`SIZE`](Self::SIZE) bytes of `bytes`.
+    fn write_bytes(self, bytes: &mut [u8]);
+}
+
+/// Stored as one byte per channel in RGBA order.
+impl BytePixel for Rgba8 {
+    const SIZE: usize = 4;
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Self {
+        Self::from_array([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    #[inline]
+    fn write_bytes(self, bytes: &mut [u8]) {
+        bytes[..4].copy_from_slice(&[self.r, self.g, self.b, self.a]);
+    }
+}
+
+/// Stored as native-endian `f32` channels in RGB order.
+impl BytePixel for Rgb32F {
+    const SIZE: usize = 12;
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Self {
+        let channel =
+            |index: usize| f32::from_ne_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap());
+
+        Self {
+            r: channel(0),
+            g: channel(1),
+            b: channel(2),
+        }
+    }
+
+    #[inline]
+    fn write_bytes(self, bytes: &mut [u8]) {
+        bytes[0..4].copy_from_slice(&self.r.to_ne_bytes());
+        bytes[4..8].copy_from_slice(&self.g.to_ne_bytes());
+        bytes[8..12].copy_from_slice(&self.b.to_ne_bytes());
+    }
+}
 
 #[derive(Debug)]
 pub struct StreamDecoder<D> {
@@ -14,150 +68,808 @@ impl<D> StreamDecoder<D> {
         Self { _decoder: decoder }
     }
 
-    pub fn decode(
+    /// Decodes `buf` into the `width` by `height` texels at the start of
+    /// `out`, where rows start every `row_pitch` texels.
+    ///
+    /// Texels between the end of a row and the start of the next row are left
+    /// untouched, so `out` can be a sub-rectangle of a larger image.
+    ///
+    /// Returns [`Error::UnsupportedMode`] if a block uses a reserved mode.
+    /// Texels of the blocks before it have already been written.
+    pub fn decode_to_slice(
         &mut self,
-        mut buf: &[u8],
+        buf: &[u8],
         width: u32,
         height: u32,
-    ) -> ImageBuffer<Rgba<u8>, Vec<u8>>
+        out: &mut [D::Pixel],
+        row_pitch: usize,
+    ) -> Result<(), Error>
     where
         D: Decoder,
     {
-        assert!(width % D::BLOCK_SIZE as u32 == 0);
-        assert!(height % D::BLOCK_SIZE as u32 == 0);
+        check_strided(out.len(), width, height, 1, row_pitch)?;
 
-        assert_eq!(
-            buf.len(),
-            width as usize * height as usize / (D::NUM_PIXELS * D::NUM_PIXELS / D::BLOCK_SIZE)
-        );
+        decode_blocks::<D>(buf, width, height, |x, y, pixel| {
+            out[y as usize * row_pitch + x as usize] = pixel;
+        })
+    }
 
-        let mut current_width = 0;
-        let mut current_height = 0;
+    /// Decodes `buf` into the `width` by `height` texels at the start of
+    /// `out`, where rows start every `row_pitch` bytes.
+    ///
+    /// Bytes between the end of a row and the start of the next row are left
+    /// untouched, so `out` can be a sub-rectangle of a larger image or a
+    /// staging buffer with an aligned row pitch.
+    ///
+    /// Returns [`Error::UnsupportedMode`] if a block uses a reserved mode.
+    /// Texels of the blocks before it have already been written.
+    pub fn decode_to_bytes(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        out: &mut [u8],
+        row_pitch: usize,
+    ) -> Result<(), Error>
+    where
+        D: Decoder,
+        D::Pixel: BytePixel,
+    {
+        check_strided(out.len(), width, height, D::Pixel::SIZE, row_pitch)?;
 
-        let mut img = ImageBuffer::new(width, height);
+        decode_blocks::<D>(buf, width, height, |x, y, pixel| {
+            let offset = y as usize * row_pitch + x as usize * D::Pixel::SIZE;
+            pixel.write_bytes(&mut out[offset..]);
+        })
+    }
+}
 
-        while current_width < width && current_height < height {
-            let block = &buf[0..D::BLOCK_SIZE];
-            buf = &buf[D::BLOCK_SIZE..];
+pub struct StreamEncoder<T> {
+    _encoder: T,
+    settings: EncodeSettings,
+}
+
+impl<T> StreamEncoder<T> {
+    pub fn new(encoder: T) -> Self {
+        Self::with_settings(encoder, EncodeSettings::DEFAULT)
+    }
 
-            let mut output = [Rgba8::MIN; 32];
-            D::decode(block, &mut output);
+    /// Creates an encoder that passes `settings` to every block.
+    pub fn with_settings(encoder: T, settings: EncodeSettings) -> Self {
+        Self {
+            _encoder: encoder,
+            settings,
+        }
+    }
 
-            for (index, pixel) in output.into_iter().enumerate() {
-                if index >= D::NUM_PIXELS * D::NUM_PIXELS {
-                    break;
-                }
+    /// Encodes the `width` by `height` texels at the start of `input`, where
+    /// rows start every `row_pitch` texels.
+    pub fn encode_slice(
+        &mut self,
+        input: &[T::Pixel],
+        width: u32,
+        height: u32,
+        row_pitch: usize,
+    ) -> Result<Vec<u8>, Error>
+    where
+        T: Encoder,
+    {
+        check_strided(input.len(), width, height, 1, row_pitch)?;
 
-                let offset_w = (index % 4) as u32;
-                let offset_h = (index / 4) as u32;
+        encode_blocks::<T>(width, height, &self.settings, |x, y| {
+            input[y as usize * row_pitch + x as usize]
+        })
+    }
 
-                img.put_pixel(
-                    current_width + offset_w,
-                    current_height + offset_h,
-                    Rgba([pixel.r, pixel.g, pixel.b, pixel.a]),
-                );
-            }
+    /// Encodes the `width` by `height` texels at the start of `input`, where
+    /// rows start every `row_pitch` bytes.
+    pub fn encode_bytes(
+        &mut self,
+        input: &[u8],
+        width: u32,
+        height: u32,
+        row_pitch: usize,
+    ) -> Result<Vec<u8>, Error>
+    where
+        T: Encoder,
+        T::Pixel: BytePixel,
+    {
+        check_strided(input.len(), width, height, T::Pixel::SIZE, row_pitch)?;
+
+        encode_blocks::<T>(width, height, &self.settings, |x, y| {
+            let offset = y as usize * row_pitch + x as usize * T::Pixel::SIZE;
+            T::Pixel::read_bytes(&input[offset..])
+        })
+    }
+}
+
+/// Decodes all blocks of `buf`, passing every texel inside of the image to
+/// `put`. Texels of edge blocks outside of the image are skipped.
+///
+/// Stops at the first block that cannot be decoded.
+fn decode_blocks<D: Decoder>(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    mut put: impl FnMut(u32, u32, D::Pixel),
+) -> Result<(), Error> {
+    let blocks_x = surface_size::<D>(buf, width, height)?;
+
+    let mut output = vec![D::Pixel::default(); D::BLOCK_WIDTH * D::BLOCK_HEIGHT];
+    for (index, bytes) in buf.chunks_exact(D::BLOCK_SIZE).enumerate() {
+        let block_x = (index % blocks_x * D::BLOCK_WIDTH) as u32;
+        let block_y = (index / blocks_x * D::BLOCK_HEIGHT) as u32;
+
+        let mut block = D::Block::default();
+        block.as_mut().copy_from_slice(bytes);
+        D::try_decode_block(&block, &mut output)?;
+
+        for (index, pixel) in output.iter().enumerate() {
+            let x = block_x + (index % D::BLOCK_WIDTH) as u32;
+            let y = block_y + (index / D::BLOCK_WIDTH) as u32;
 
-            current_width += 4;
-            if current_width == width {
-                current_width = 0;
-                current_height += 4;
+            if x < width && y < height {
+                put(x, y, *pixel);
             }
         }
+    }
+
+    Ok(())
+}
+
+/// Encodes an image, reading texels with `get`. Edge blocks are padded by
+/// clamping the coordinates to the image.
+fn encode_blocks<T: Encoder>(
+    width: u32,
+    height: u32,
+    settings: &EncodeSettings,
+    get: impl Fn(u32, u32) -> T::Pixel,
+) -> Result<Vec<u8>, Error> {
+    let (blocks_x, len) = block_count::<T>(width, height)?;
+    let mut output = Vec::with_capacity(len);
+
+    let mut input = vec![T::Pixel::default(); T::BLOCK_WIDTH * T::BLOCK_HEIGHT];
+    for index in 0..len / T::BLOCK_SIZE {
+        let block_x = (index % blocks_x * T::BLOCK_WIDTH) as u32;
+        let block_y = (index / blocks_x * T::BLOCK_HEIGHT) as u32;
+
+        for (index, texel) in input.iter_mut().enumerate() {
+            let x = (block_x + (index % T::BLOCK_WIDTH) as u32).min(width - 1);
+            let y = (block_y + (index / T::BLOCK_WIDTH) as u32).min(height - 1);
+
+            *texel = get(x, y);
+        }
 
-        img
+        let block = T::encode_block(&input, settings);
+        output.extend_from_slice(block.as_ref());
     }
+
+    Ok(output)
 }
 
-pub struct StreamEncoder<T> {
-    _encoder: T,
+/// Returns the number of blocks in a row and the size of the compressed data
+/// of an image in bytes, rounding partial blocks up.
+///
+/// Returns [`Error::UnsupportedFormat`] if the constants of the format do not
+/// describe a non-empty block of the size of [`BlockFormat::Block`].
+fn block_count<T: BlockFormat>(width: u32, height: u32) -> Result<(usize, usize), Error> {
+    if T::BLOCK_WIDTH == 0
+        || T::BLOCK_HEIGHT == 0
+        || T::BLOCK_SIZE == 0
+        || T::BLOCK_SIZE != T::Block::default().as_ref().len()
+    {
+        return Err(Error::UnsupportedFormat);
+    }
+
+    let blocks_x = (width as usize).div_ceil(T::BLOCK_WIDTH);
+    let blocks_y = (height as usize).div_ceil(T::BLOCK_HEIGHT);
+
+    let len = blocks_x
+        .checked_mul(blocks_y)
+        .and_then(|blocks| blocks.checked_mul(T::BLOCK_SIZE))
+        .ok_or(Error::InvalidDimensions { width, height })?;
+
+    Ok((blocks_x, len))
 }
 
-impl<T> StreamEncoder<T> {
-    pub fn new(encoder: T) -> Self {
-        Self { _encoder: encoder }
+/// Checks that `buf` holds the compressed data of an image and returns the
+/// number of blocks in a row.
+fn surface_size<T: BlockFormat>(buf: &[u8], width: u32, height: u32) -> Result<usize, Error> {
+    let (blocks_x, len) = block_count::<T>(width, height)?;
+    if buf.len() != len {
+        return Err(Error::InvalidLength {
+            expected: len,
+            actual: buf.len(),
+        });
+    }
+
+    Ok(blocks_x)
+}
+
+/// Checks that a buffer of `len` units holds `height` rows of `width` texels
+/// of `texel_size` units, with rows starting every `row_pitch` units.
+fn check_strided(
+    len: usize,
+    width: u32,
+    height: u32,
+    texel_size: usize,
+    row_pitch: usize,
+) -> Result<(), Error> {
+    let invalid = Error::InvalidDimensions { width, height };
+
+    let row_len = (width as usize).checked_mul(texel_size).ok_or(invalid)?;
+    if row_pitch < row_len {
+        return Err(invalid);
+    }
+
+    // The last row does not need any padding.
+    let expected = match (height as usize).checked_sub(1) {
+        Some(rows) => rows
+            .checked_mul(row_pitch)
+            .and_then(|v| v.checked_add(row_len))
+            .ok_or(invalid)?,
+        None => 0,
+    };
+
+    if len < expected {
+        return Err(Error::InvalidLength {
+            expected,
+            actual: len,
+        });
+    }
+
+    Ok(())
+}
+
+/// A texel that can be converted to and from a pixel of an [`ImageBuffer`].
+#[cfg(feature = "image")]
+pub trait ImagePixel: Copy {
+    type Pixel: Pixel;
+
+    fn from_pixel(pixel: Self::Pixel) -> Self;
+
+    fn to_pixel(self) -> Self::Pixel;
+
+    /// Converts `img` to an image with pixels of this type.
+    fn from_dynamic(img: &DynamicImage) -> ImageBuffer<Self::Pixel, Vec<Subpixel<Self>>>;
+
+    fn to_dynamic(img: ImageBuffer<Self::Pixel, Vec<Subpixel<Self>>>) -> DynamicImage;
+}
+
+#[cfg(feature = "image")]
+type Subpixel<T> = <<T as ImagePixel>::Pixel as Pixel>::Subpixel;
+
+#[cfg(feature = "image")]
+impl ImagePixel for Rgba8 {
+    type Pixel = Rgba<u8>;
+
+    #[inline]
+    fn from_pixel(pixel: Rgba<u8>) -> Self {
+        Self::from_array(pixel.0)
+    }
+
+    #[inline]
+    fn to_pixel(self) -> Rgba<u8> {
+        Rgba([self.r, self.g, self.b, self.a])
     }
 
-    pub fn encode<C>(&mut self, img: &ImageBuffer<Rgba<u8>, C>, width: u32, height: u32) -> Vec<u8>
+    fn from_dynamic(img: &DynamicImage) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        img.to_rgba8()
+    }
+
+    fn to_dynamic(img: ImageBuffer<Rgba<u8>, Vec<u8>>) -> DynamicImage {
+        DynamicImage::ImageRgba8(img)
+    }
+}
+
+#[cfg(feature = "image")]
+impl ImagePixel for Rgb32F {
+    type Pixel = Rgb<f32>;
+
+    #[inline]
+    fn from_pixel(pixel: Rgb<f32>) -> Self {
+        Self {
+            r: pixel.0[0],
+            g: pixel.0[1],
+            b: pixel.0[2],
+        }
+    }
+
+    #[inline]
+    fn to_pixel(self) -> Rgb<f32> {
+        Rgb([self.r, self.g, self.b])
+    }
+
+    fn from_dynamic(img: &DynamicImage) -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+        img.to_rgb32f()
+    }
+
+    fn to_dynamic(img: ImageBuffer<Rgb<f32>, Vec<f32>>) -> DynamicImage {
+        DynamicImage::ImageRgb32F(img)
+    }
+}
+
+#[cfg(feature = "image")]
+impl<D> StreamDecoder<D> {
+    /// Decodes `buf` into an image of `width` by `height` pixels.
+    ///
+    /// The dimensions do not need to be a multiple of the block size. Edge
+    /// blocks are cropped to the image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` does not have the length required by the dimensions or
+    /// a block uses a reserved mode. See [`try_decode`](Self::try_decode) for
+    /// a non-panicking version.
+    pub fn decode<P>(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>>
     where
-        T: Encoder,
-        C: core::ops::Deref<Target = [u8]>,
+        D: Decoder,
+        D::Pixel: ImagePixel<Pixel = P>,
+        P: Pixel,
+    {
+        self.try_decode(buf, width, height)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Decodes `buf` into an image of `width` by `height` pixels, returning an
+    /// error if `buf` does not have the length required by the dimensions or
+    /// a block uses a reserved mode.
+    pub fn try_decode<P>(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error>
+    where
+        D: Decoder,
+        D::Pixel: ImagePixel<Pixel = P>,
+        P: Pixel,
     {
-        let mut current_height = 0;
-        let mut current_width = 0;
+        // Check the length before allocating the image.
+        surface_size::<D>(buf, width, height)?;
 
-        let mut output = Vec::with_capacity(
-            width as usize * height as usize / (T::NUM_PIXELS * T::NUM_PIXELS / T::BLOCK_SIZE),
-        );
+        let mut img = ImageBuffer::new(width, height);
+        decode_blocks::<D>(buf, width, height, |x, y, pixel| {
+            img.put_pixel(x, y, pixel.to_pixel());
+        })?;
 
-        while current_width < width && current_height < height {
-            let mut block = [Rgba8::MIN; 32];
+        Ok(img)
+    }
 
-            for index in 0..T::NUM_PIXELS * T::NUM_PIXELS {
-                let offset_x = (index % T::NUM_PIXELS) as u32;
-                let offset_y = (index / T::NUM_PIXELS) as u32;
+    /// Decodes `buf` into a [`DynamicImage`] of `width` by `height` pixels.
+    pub fn decode_dynamic(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<DynamicImage, Error>
+    where
+        D: Decoder,
+        D::Pixel: ImagePixel,
+    {
+        let img = self.try_decode(buf, width, height)?;
+        Ok(D::Pixel::to_dynamic(img))
+    }
+}
 
-                let px = img.get_pixel(current_width + offset_x, current_height + offset_y);
-                block[index] = Rgba8::from_array(px.0);
-            }
+#[cfg(feature = "image")]
+impl<T> StreamEncoder<T> {
+    /// Encodes the first `width` by `height` pixels of `img`.
+    ///
+    /// The dimensions do not need to be a multiple of the block size. Edge
+    /// blocks are padded by repeating the last row and column of the image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `img` is smaller than the dimensions. See
+    /// [`try_encode`](Self::try_encode) for a non-panicking version.
+    pub fn encode<P, C>(&mut self, img: &ImageBuffer<P, C>, width: u32, height: u32) -> Vec<u8>
+    where
+        T: Encoder,
+        T::Pixel: ImagePixel<Pixel = P>,
+        P: Pixel,
+        C: core::ops::Deref<Target = [P::Subpixel]>,
+    {
+        self.try_encode(img, width, height)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
 
-            let mut out = [0; 32];
-            T::encode(
-                &block[..T::NUM_PIXELS * T::NUM_PIXELS],
-                &mut out[..T::BLOCK_SIZE],
-            );
-            output.extend(&out[..T::BLOCK_SIZE]);
-
-            current_width += 4;
-            if current_width == width {
-                current_width = 0;
-                current_height += 4;
-            }
+    /// Encodes the first `width` by `height` pixels of `img`, returning an
+    /// error if `img` is smaller than the dimensions.
+    pub fn try_encode<P, C>(
+        &mut self,
+        img: &ImageBuffer<P, C>,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, Error>
+    where
+        T: Encoder,
+        T::Pixel: ImagePixel<Pixel = P>,
+        P: Pixel,
+        C: core::ops::Deref<Target = [P::Subpixel]>,
+    {
+        if width > img.width() || height > img.height() {
+            return Err(Error::InvalidDimensions { width, height });
         }
 
-        output
+        encode_blocks::<T>(width, height, &self.settings, |x, y| {
+            T::Pixel::from_pixel(*img.get_pixel(x, y))
+        })
+    }
+
+    /// Encodes `img`, converting it to the pixel type of the format first.
+    pub fn encode_dynamic(&mut self, img: &DynamicImage) -> Result<Vec<u8>, Error>
+    where
+        T: Encoder,
+        T::Pixel: ImagePixel,
+    {
+        let img = T::Pixel::from_dynamic(img);
+        self.try_encode(&img, img.width(), img.height())
     }
 }
 
+#[cfg(feature = "image")]
 pub fn decode_bc1_stream(
-    mut buf: &[u8],
+    buf: &[u8],
     width: u32,
     height: u32,
 ) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
-    // assert!(width % 4 == 0);
-    // assert!(height % 4 == 0);
+    StreamDecoder::new(Bc1).decode(buf, width, height)
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use alloc::vec::Vec;
+    #[cfg(feature = "image")]
+    use image::{DynamicImage, ImageBuffer, Rgba};
+
+    use crate::bc1::Bc1;
+    #[cfg(feature = "image")]
+    use crate::bc6h::Bc6hUf16;
+    use crate::bc7::Bc7;
+    use crate::{Block8, BlockFormat, Decoder, EncodeSettings, Encoder, Error, Rgba8};
 
-    // // BC1 converts 8 byte blocks into 16 pixels each.
-    // assert_eq!(buf.len(), width as usize * height as usize / 2);
+    use super::{StreamDecoder, StreamEncoder};
 
-    // let mut current_width = 0;
-    // let mut current_height = 0;
+    fn gradient_texel(x: u32, y: u32) -> [u8; 4] {
+        [(x * 16) as u8, (y * 16) as u8, 128, 255]
+    }
 
-    // let mut img = ImageBuffer::new(width, height);
+    /// Returns the texels of a gradient row by row, without padding.
+    fn gradient_texels(width: u32, height: u32) -> Vec<Rgba8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| Rgba8::from_array(gradient_texel(x, y))))
+            .collect()
+    }
 
-    // while current_width < width && current_height < height {
-    //     let block = buf[0..8].try_into().unwrap();
-    //     buf = &buf[8..];
+    #[cfg(feature = "image")]
+    fn gradient(width: u32, height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(width, height, |x, y| Rgba(gradient_texel(x, y)))
+    }
 
-    //     let output = crate::bc1::decode(block);
-    //     for (index, pixel) in output.into_iter().enumerate() {
-    //         let offset_w = 3 - (index % 4) as u32;
-    //         let offset_h = (index / 4) as u32;
+    #[test]
+    #[cfg(feature = "image")]
+    fn roundtrip_unaligned() {
+        for (width, height) in [(1, 1), (2, 2), (5, 3), (4, 9), (13, 7)] {
+            let img = gradient(width, height);
 
-    //         img.put_pixel(
-    //             current_width + offset_w,
-    //             current_height + offset_h,
-    //             Rgb([pixel.r, pixel.g, pixel.b]),
-    //         );
-    //     }
+            let buf = StreamEncoder::new(Bc7).encode(&img, width, height);
+            let blocks = width.div_ceil(4) * height.div_ceil(4);
+            assert_eq!(buf.len(), blocks as usize * 16);
 
-    //     current_width += 4;
-    //     if current_width == width {
-    //         current_width = 0;
-    //         current_height += 4;
-    //     }
-    // }
+            let output = StreamDecoder::new(Bc7).decode(&buf, width, height);
+            assert_eq!(output.dimensions(), (width, height));
 
-    // img
-    StreamDecoder::new(Bc1).decode(buf, width, height)
+            for (lhs, rhs) in img.pixels().zip(output.pixels()) {
+                for (lhs, rhs) in lhs.0.iter().zip(rhs.0) {
+                    assert!(lhs.abs_diff(rhs) <= 8, "{width}x{height}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn pad_edge_texels() {
+        // A single pixel padded to a full block is a solid color.
+        let img = ImageBuffer::from_pixel(1, 1, Rgba([200u8, 100, 50, 255]));
+        let buf = StreamEncoder::new(Bc1).encode(&img, 1, 1);
+        assert_eq!(buf.len(), 8);
+
+        let output = StreamDecoder::new(Bc1).decode(&buf, 4, 4);
+        let first = output.get_pixel(0, 0);
+        assert!(output.pixels().all(|px| px == first));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn empty() {
+        let img = ImageBuffer::<Rgba<u8>, _>::new(0, 0);
+        let buf = StreamEncoder::new(Bc1).encode(&img, 0, 0);
+        assert!(buf.is_empty());
+
+        let output = StreamDecoder::new(Bc1).decode::<Rgba<u8>>(&buf, 0, 0);
+        assert_eq!(output.dimensions(), (0, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn try_decode_length() {
+        let buf = [0; 24];
+        let err = StreamDecoder::new(Bc1)
+            .try_decode::<Rgba<u8>>(&buf, 5, 5)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidLength {
+                expected: 32,
+                actual: 24
+            }
+        );
+
+        assert!(StreamDecoder::new(Bc1)
+            .try_decode::<Rgba<u8>>(&buf, 12, 4)
+            .is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn try_decode_reserved_mode() {
+        // The second block of BC7 is all zeros, which is a reserved mode.
+        let mut buf = StreamEncoder::new(Bc7).encode(&gradient(4, 4), 4, 4);
+        buf.extend_from_slice(&[0; 16]);
+
+        let err = StreamDecoder::new(Bc7)
+            .try_decode::<Rgba<u8>>(&buf, 8, 4)
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedMode);
+
+        let mut out = [Rgba8::default(); 32];
+        let err = StreamDecoder::new(Bc7)
+            .decode_to_slice(&buf, 8, 4, &mut out, 8)
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedMode);
+
+        // Mode 0b10011 of BC6H is reserved.
+        let mut block = [0; 16];
+        block[0] = 0b10011;
+        let mut out = [0; 16 * 12];
+        let err = StreamDecoder::new(Bc6hUf16)
+            .decode_to_bytes(&block, 4, 4, &mut out, 48)
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedMode);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn try_encode_dimensions() {
+        let img = gradient(4, 4);
+        let err = StreamEncoder::new(Bc1).try_encode(&img, 8, 4).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidDimensions {
+                width: 8,
+                height: 4
+            }
+        );
+    }
+
+    #[test]
+    fn decode_to_slice_pitch() {
+        let buf = StreamEncoder::new(Bc1)
+            .encode_slice(&gradient_texels(6, 5), 6, 5, 6)
+            .unwrap();
+        let mut expected = [Rgba8::default(); 30];
+        StreamDecoder::new(Bc1)
+            .decode_to_slice(&buf, 6, 5, &mut expected, 6)
+            .unwrap();
+
+        // Decode into the bottom right corner of a 10x8 atlas.
+        let mut atlas = [Rgba8::from_array([1; 4]); 80];
+        StreamDecoder::new(Bc1)
+            .decode_to_slice(&buf, 6, 5, &mut atlas[3 * 10 + 4..], 10)
+            .unwrap();
+
+        for (index, texel) in atlas.iter().enumerate() {
+            let (x, y) = (index as u32 % 10, index as u32 / 10);
+            if x >= 4 && y >= 3 {
+                assert_eq!(*texel, expected[(y - 3) as usize * 6 + (x - 4) as usize]);
+            } else {
+                assert_eq!(*texel, Rgba8::from_array([1; 4]));
+            }
+        }
+    }
+
+    #[test]
+    fn decode_to_bytes_pitch() {
+        let buf = StreamEncoder::new(Bc7)
+            .encode_slice(&gradient_texels(5, 3), 5, 3, 5)
+            .unwrap();
+        let mut expected = [Rgba8::default(); 15];
+        StreamDecoder::new(Bc7)
+            .decode_to_slice(&buf, 5, 3, &mut expected, 5)
+            .unwrap();
+
+        // Rows are aligned to 32 bytes, the last row is not padded.
+        let mut out = [0xaa; 32 * 2 + 20];
+        StreamDecoder::new(Bc7)
+            .decode_to_bytes(&buf, 5, 3, &mut out, 32)
+            .unwrap();
+
+        for (y, row) in out.chunks(32).enumerate() {
+            for (x, texel) in row[..20].chunks(4).enumerate() {
+                let texel = Rgba8::from_array(texel.try_into().unwrap());
+                assert_eq!(texel, expected[y * 5 + x]);
+            }
+            assert!(row[20..].iter().all(|b| *b == 0xaa));
+        }
+
+        let err = StreamDecoder::new(Bc7)
+            .decode_to_bytes(&buf, 5, 3, &mut out[..83], 32)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidLength {
+                expected: 84,
+                actual: 83
+            }
+        );
+    }
+
+    #[test]
+    fn encode_strided() {
+        let mut encoder = StreamEncoder::new(Bc1);
+        let expected = encoder
+            .encode_slice(&gradient_texels(7, 6), 7, 6, 7)
+            .unwrap();
+
+        let mut bytes = alloc::vec![0; 40 * 6];
+        let mut texels = alloc::vec![Rgba8::default(); 9 * 6];
+        for y in 0..6 {
+            for x in 0..7 {
+                let texel = gradient_texel(x, y);
+                let offset = y as usize * 40 + x as usize * 4;
+                bytes[offset..offset + 4].copy_from_slice(&texel);
+                texels[y as usize * 9 + x as usize] = Rgba8::from_array(texel);
+            }
+        }
+
+        assert_eq!(encoder.encode_bytes(&bytes, 7, 6, 40).unwrap(), expected);
+        assert_eq!(encoder.encode_slice(&texels, 7, 6, 9).unwrap(), expected);
+
+        // The pitch must cover a full row.
+        let err = encoder.encode_slice(&texels, 7, 6, 6).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidDimensions {
+                width: 7,
+                height: 6
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn dynamic_roundtrip() {
+        let img = DynamicImage::ImageRgba8(gradient(6, 6));
+
+        let buf = StreamEncoder::new(Bc7).encode_dynamic(&img).unwrap();
+        let output = StreamDecoder::new(Bc7).decode_dynamic(&buf, 6, 6).unwrap();
+        assert_eq!(
+            output.to_rgba8(),
+            StreamDecoder::new(Bc7).decode(&buf, 6, 6)
+        );
+
+        // HDR formats convert to floating point pixels.
+        let buf = StreamEncoder::new(Bc6hUf16).encode_dynamic(&img).unwrap();
+        let output = StreamDecoder::new(Bc6hUf16)
+            .decode_dynamic(&buf, 6, 6)
+            .unwrap();
+        assert!(matches!(output, DynamicImage::ImageRgb32F(_)));
+    }
+
+    /// Stores two texels side by side without compression.
+    struct Raw2x1;
+
+    impl BlockFormat for Raw2x1 {
+        type Pixel = Rgba8;
+        type Block = Block8;
+
+        const BLOCK_WIDTH: usize = 2;
+        const BLOCK_HEIGHT: usize = 1;
+        const BLOCK_SIZE: usize = 8;
+    }
+
+    impl Decoder for Raw2x1 {
+        fn decode_block(block: &Block8, out: &mut [Rgba8]) {
+            out[0] = Rgba8::from_array(block[..4].try_into().unwrap());
+            out[1] = Rgba8::from_array(block[4..].try_into().unwrap());
+        }
+    }
+
+    impl Encoder for Raw2x1 {
+        fn encode_block(input: &[Rgba8], _settings: &EncodeSettings) -> Block8 {
+            let mut block = [0; 8];
+            block[..4].copy_from_slice(&[input[0].r, input[0].g, input[0].b, input[0].a]);
+            block[4..].copy_from_slice(&[input[1].r, input[1].g, input[1].b, input[1].a]);
+            block
+        }
+    }
+
+    /// A format whose block size does not match its block type.
+    struct InvalidBlockSize;
+
+    impl BlockFormat for InvalidBlockSize {
+        type Pixel = Rgba8;
+        type Block = Block8;
+
+        const BLOCK_WIDTH: usize = 2;
+        const BLOCK_HEIGHT: usize = 1;
+        const BLOCK_SIZE: usize = 16;
+    }
+
+    impl Decoder for InvalidBlockSize {
+        fn decode_block(block: &Block8, out: &mut [Rgba8]) {
+            Raw2x1::decode_block(block, out);
+        }
+    }
+
+    impl Encoder for InvalidBlockSize {
+        fn encode_block(input: &[Rgba8], settings: &EncodeSettings) -> Block8 {
+            Raw2x1::encode_block(input, settings)
+        }
+    }
+
+    /// A format with empty blocks.
+    struct EmptyBlock;
+
+    impl BlockFormat for EmptyBlock {
+        type Pixel = Rgba8;
+        type Block = Block8;
+
+        const BLOCK_WIDTH: usize = 0;
+        const BLOCK_HEIGHT: usize = 1;
+        const BLOCK_SIZE: usize = 8;
+    }
+
+    impl Decoder for EmptyBlock {
+        fn decode_block(_block: &Block8, _out: &mut [Rgba8]) {}
+    }
+
+    #[test]
+    fn invalid_format_constants() {
+        let err = StreamEncoder::new(InvalidBlockSize)
+            .encode_slice(&gradient_texels(4, 4), 4, 4, 4)
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedFormat);
+
+        let buf = [0; 64];
+        let mut out = [Rgba8::default(); 16];
+        let err = StreamDecoder::new(InvalidBlockSize)
+            .decode_to_slice(&buf, 4, 4, &mut out, 4)
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedFormat);
+
+        let err = StreamDecoder::new(EmptyBlock)
+            .decode_to_slice(&buf, 4, 4, &mut out, 4)
+            .unwrap_err();
+        assert_eq!(err, Error::UnsupportedFormat);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn custom_format() {
+        let img = gradient(3, 3);
+
+        let buf = StreamEncoder::new(Raw2x1).encode(&img, 3, 3);
+        assert_eq!(buf.len(), 2 * 3 * 8);
+
+        let output = StreamDecoder::new(Raw2x1).decode(&buf, 3, 3);
+        assert_eq!(output, img);
+    }
 }
@@ -0,0 +1,17 @@
+//! Arithmetic on `[f32; 3]` colors, shared by the BC1 and BC6H encoders.
+
+pub(crate) fn add(lhs: [f32; 3], rhs: [f32; 3]) -> [f32; 3] {
+    [lhs[0] + rhs[0], lhs[1] + rhs[1], lhs[2] + rhs[2]]
+}
+
+pub(crate) fn sub(lhs: [f32; 3], rhs: [f32; 3]) -> [f32; 3] {
+    [lhs[0] - rhs[0], lhs[1] - rhs[1], lhs[2] - rhs[2]]
+}
+
+pub(crate) fn scale(v: [f32; 3], s: f32) -> [f32; 3] {
+    v.map(|v| v * s)
+}
+
+pub(crate) fn dot(lhs: [f32; 3], rhs: [f32; 3]) -> f32 {
+    lhs[0] * rhs[0] + lhs[1] * rhs[1] + lhs[2] * rhs[2]
+}